@@ -0,0 +1,357 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio::SAMPLE_RATE;
+use crate::config::AecConfig;
+
+/// Never keep more than this much unconsumed reference audio; if the mic
+/// side stalls, the oldest samples are dropped.
+const MAX_REFERENCE_SAMPLES: usize = SAMPLE_RATE as usize;
+const TAP_BATCH: usize = 256;
+/// Reference audio buffered before the canceller starts consuming it.
+const JITTER_SAMPLES: usize = 1024;
+/// Reference energy below which the filter is considered idle.
+const SILENT_POWER: f32 = 1e-6;
+
+// ---------------------------------------------------------------------------
+// EchoReference — what the speaker is playing, resampled to 16 kHz
+// ---------------------------------------------------------------------------
+
+/// Shared queue of samples sent to the output device.  Playback pushes into
+/// it through `tap`, the echo canceller drains it one mic chunk at a time.
+#[derive(Clone, Default)]
+pub struct EchoReference {
+    queue: Arc<Mutex<ReferenceQueue>>,
+}
+
+#[derive(Default)]
+struct ReferenceQueue {
+    samples: VecDeque<f32>,
+    /// Enough audio has been buffered to absorb the bursty pulls of the
+    /// output device without running dry mid-playback.
+    primed: bool,
+}
+
+impl EchoReference {
+    /// Wrap a playback source so every sample it yields is also recorded as
+    /// echo reference.
+    pub fn tap<S: Source<Item = f32>>(&self, source: S) -> EchoTap<S> {
+        EchoTap {
+            channels: source.channels().max(1),
+            rate: source.sample_rate(),
+            lowpass: Lowpass::new(source.sample_rate()),
+            inner: source,
+            reference: self.clone(),
+            channel: 0,
+            phase: 0,
+            batch: Vec::with_capacity(TAP_BATCH),
+        }
+    }
+
+    /// Take the next `n` reference samples, zero-padded when nothing is playing.
+    fn take(&self, n: usize) -> Vec<f32> {
+        let mut queue = self.queue.lock().unwrap();
+        let len = queue.samples.len();
+        if !queue.primed {
+            if len < n + JITTER_SAMPLES {
+                return vec![0.0; n];
+            }
+            queue.primed = true;
+        }
+        if len < n {
+            // Ran dry: playback has ended.
+            queue.primed = false;
+        }
+        let mut out: Vec<f32> = queue.samples.drain(..n.min(len)).collect();
+        out.resize(n, 0.0);
+        out
+    }
+
+    fn push(&self, samples: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.samples.extend(samples);
+        let excess = queue.samples.len().saturating_sub(MAX_REFERENCE_SAMPLES);
+        queue.samples.drain(..excess);
+    }
+}
+
+pub struct EchoTap<S> {
+    inner: S,
+    reference: EchoReference,
+    channels: u16,
+    rate: u32,
+    channel: u16,
+    phase: u32,
+    lowpass: Lowpass,
+    batch: Vec<f32>,
+}
+
+impl<S: Source<Item = f32>> Iterator for EchoTap<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let Some(sample) = self.inner.next() else {
+            self.reference.push(&self.batch);
+            self.batch.clear();
+            return None;
+        };
+
+        // First channel only, band-limited and decimated to 16 kHz by phase
+        // accumulation.
+        if self.channel == 0 {
+            let filtered = self.lowpass.process(sample);
+            self.phase += SAMPLE_RATE;
+            while self.phase >= self.rate {
+                self.phase -= self.rate;
+                self.batch.push(filtered);
+            }
+            if self.batch.len() >= TAP_BATCH {
+                self.reference.push(&self.batch);
+                self.batch.clear();
+            }
+        }
+        self.channel = (self.channel + 1) % self.channels;
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for EchoTap<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Second-order Butterworth low-pass below the 8 kHz Nyquist limit, so
+/// higher playback frequencies do not alias into the reference.  A pass-through
+/// for sources at or below 16 kHz.
+struct Lowpass {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Lowpass {
+    const CUTOFF_HZ: f32 = 7000.0;
+
+    fn new(rate: u32) -> Self {
+        if rate <= SAMPLE_RATE {
+            return Self {
+                b: [1.0, 0.0, 0.0],
+                a: [0.0, 0.0],
+                x: [0.0; 2],
+                y: [0.0; 2],
+            };
+        }
+        // RBJ cookbook, Q = 1/√2.
+        let w = 2.0 * std::f32::consts::PI * Self::CUTOFF_HZ / rate as f32;
+        let alpha = w.sin() / std::f32::consts::SQRT_2;
+        let a0 = 1.0 + alpha;
+        let k = (1.0 - w.cos()) / a0;
+        Self {
+            b: [k / 2.0, k, k / 2.0],
+            a: [-2.0 * w.cos() / a0, (1.0 - alpha) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+// ---------------------------------------------------------------------------
+// EchoCanceller — NLMS adaptive filter with Geigel double-talk detection
+// ---------------------------------------------------------------------------
+
+pub struct EchoCanceller {
+    reference: EchoReference,
+    /// Adaptive filter taps, newest reference sample first.
+    weights: Vec<f32>,
+    /// Reference history: `delay` samples of bulk delay followed by the
+    /// `weights.len()` samples the filter sees.  Every sample is stored twice,
+    /// `len` apart, so `history[pos..pos + len]` is always one contiguous
+    /// slice, newest first.
+    history: Vec<f32>,
+    pos: usize,
+    len: usize,
+    delay: usize,
+    step: f32,
+    /// Running energy of the samples under the filter.
+    power: f32,
+}
+
+impl EchoCanceller {
+    pub fn new(config: &AecConfig, reference: EchoReference) -> Self {
+        let taps = (config.filter_ms * SAMPLE_RATE as u64 / 1000).max(1) as usize;
+        let delay = (config.delay_ms * SAMPLE_RATE as u64 / 1000) as usize;
+        let len = delay + taps;
+        Self {
+            reference,
+            weights: vec![0.0; taps],
+            history: vec![0.0; 2 * len],
+            pos: 0,
+            len,
+            delay,
+            step: config.step,
+            power: 0.0,
+        }
+    }
+
+    /// Subtract the estimated speaker echo from a chunk of mic audio in place.
+    pub fn process(&mut self, mic: &mut [i16]) {
+        let far = self.reference.take(mic.len());
+
+        // Nothing played recently: skip the filter work entirely.
+        if far.iter().all(|&s| s == 0.0) && self.power < SILENT_POWER {
+            self.power = 0.0;
+            self.advance_silence(far.len());
+            return;
+        }
+
+        let taps = self.weights.len();
+        // Geigel threshold for the whole chunk: the far-end peak over every
+        // sample any window in this chunk will see.
+        let mut peak = self.window().iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        for &x in &far {
+            peak = peak.max(x.abs());
+        }
+
+        for (sample, &x) in mic.iter_mut().zip(&far) {
+            // Shift the new reference sample in, keep the windowed energy current.
+            let leaving = self.push(x);
+            let entering = self.history[self.pos + self.delay];
+            self.power = (self.power + entering * entering - leaving * leaving).max(0.0);
+
+            let start = self.pos + self.delay;
+            let window = &self.history[start..start + taps];
+            let d = *sample as f32 / i16::MAX as f32;
+            let y: f32 = self.weights.iter().zip(window).map(|(w, x)| w * x).sum();
+            let e = d - y;
+
+            // Geigel: a near-end louder than half the recent far-end peak is
+            // the user talking — freeze adaptation so the filter does not diverge.
+            if d.abs() < 0.5 * peak {
+                let g = self.step * e / (self.power + 1e-6);
+                for (w, x) in self.weights.iter_mut().zip(window) {
+                    *w += g * x;
+                }
+            }
+
+            *sample = (e.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        }
+    }
+
+    /// The reference samples currently under the filter, newest first.
+    fn window(&self) -> &[f32] {
+        let start = self.pos + self.delay;
+        &self.history[start..start + self.weights.len()]
+    }
+
+    /// Shift one reference sample in and return the one that fell out.
+    fn push(&mut self, x: f32) -> f32 {
+        self.pos = if self.pos == 0 {
+            self.len - 1
+        } else {
+            self.pos - 1
+        };
+        let leaving = self.history[self.pos];
+        self.history[self.pos] = x;
+        self.history[self.pos + self.len] = x;
+        leaving
+    }
+
+    /// Keep the history aligned with real time while nothing is playing.
+    fn advance_silence(&mut self, n: usize) {
+        for _ in 0..n.min(self.len) {
+            self.push(0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic white-ish noise in [-0.5, 0.5).
+    fn noise(n: usize) -> Vec<f32> {
+        let mut state = 0x1234_5678u32;
+        (0..n)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect()
+    }
+
+    fn energy(chunk: &[i16]) -> f64 {
+        chunk.iter().map(|&s| (s as f64).powi(2)).sum()
+    }
+
+    #[test]
+    fn cancels_delayed_echo() {
+        let config = AecConfig {
+            enabled: true,
+            filter_ms: 4,
+            delay_ms: 0,
+            step: 0.5,
+        };
+        let reference = EchoReference::default();
+        let mut aec = EchoCanceller::new(&config, reference.clone());
+
+        let far = noise(12_000);
+        reference.push(&far);
+        // The mic hears the speaker 10 samples late at half volume.
+        let mic: Vec<i16> = (0..far.len())
+            .map(|i| {
+                let x = if i >= 10 { far[i - 10] } else { 0.0 };
+                (0.5 * x * i16::MAX as f32) as i16
+            })
+            .collect();
+
+        let chunks: Vec<Vec<i16>> = mic
+            .chunks(1600)
+            .map(|c| {
+                let mut c = c.to_vec();
+                aec.process(&mut c);
+                c
+            })
+            .collect();
+        let before = energy(&mic[mic.len() - 1600..]);
+        let after = energy(&chunks[chunks.len() - 2]);
+        assert!(after < before / 100.0, "residual {after} of {before}");
+    }
+
+    #[test]
+    fn lowpass_removes_content_above_nyquist() {
+        let rate = 48_000;
+        let mut lowpass = Lowpass::new(rate);
+        // 12 kHz would alias to 4 kHz after decimation to 16 kHz.
+        let out: Vec<f32> = (0..4800)
+            .map(|i| {
+                let t = i as f32 / rate as f32;
+                lowpass.process((2.0 * std::f32::consts::PI * 12_000.0 * t).sin())
+            })
+            .collect();
+        let peak = out[480..].iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        assert!(peak < 0.3, "peak {peak}");
+    }
+}
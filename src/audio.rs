@@ -0,0 +1,423 @@
+use std::fmt;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use voskrust::sound::ParecStream;
+
+use crate::config::{AudioConfig, AudioSourceKind};
+
+/// Sample rate expected by the recognizer.
+pub const SAMPLE_RATE: u32 = 16000;
+
+#[derive(Debug)]
+pub enum AudioError {
+    /// The sound server or device failed; it may come back (e.g. PulseAudio
+    /// restarting), so reopening later is worthwhile.
+    Device(String),
+    /// The configured file or stream is unusable; retrying will not help.
+    Source(String),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioError::Device(e) | AudioError::Source(e) => f.write_str(e),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// AudioSource — anything that yields 16 kHz mono PCM
+// ---------------------------------------------------------------------------
+
+pub trait AudioSource {
+    /// Read the next `ms` milliseconds of 16 kHz mono audio.
+    /// `Ok(None)` means the stream has ended and no more audio will arrive.
+    fn read_chunk(&mut self, ms: f64) -> Result<Option<Vec<i16>>, AudioError>;
+}
+
+/// Open the source selected in the `[audio]` config section.
+pub fn open(config: &AudioConfig) -> Result<Box<dyn AudioSource>, AudioError> {
+    match config.source {
+        AudioSourceKind::Parec => Ok(Box::new(ParecSource::new()?)),
+        AudioSourceKind::File => Ok(Box::new(
+            FileSource::open(config).map_err(AudioError::Source)?,
+        )),
+        AudioSourceKind::Stdin => Ok(Box::new(StdinSource::new(config))),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PulseAudio microphone via `parec`
+// ---------------------------------------------------------------------------
+
+pub struct ParecSource {
+    stream: ParecStream,
+}
+
+impl ParecSource {
+    pub fn new() -> Result<Self, AudioError> {
+        let stream =
+            ParecStream::init().map_err(|e| AudioError::Device(format!("parec: {}", e)))?;
+        Ok(Self { stream })
+    }
+}
+
+impl AudioSource for ParecSource {
+    fn read_chunk(&mut self, ms: f64) -> Result<Option<Vec<i16>>, AudioError> {
+        self.stream
+            .read_n_milliseconds(ms)
+            .map(Some)
+            .map_err(|e| AudioError::Device(format!("parec: {}", e)))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Recorded session replay (WAV or raw s16le)
+// ---------------------------------------------------------------------------
+
+pub struct FileSource {
+    samples: Vec<i16>,
+    pos: usize,
+    realtime: bool,
+    started: Option<Instant>,
+}
+
+impl FileSource {
+    /// Load the whole file into memory, converted to 16 kHz mono.
+    /// Files ending in `.wav` are decoded from their header; anything else is
+    /// treated as raw s16le with the configured rate and channel count.
+    pub fn open(config: &AudioConfig) -> Result<Self, String> {
+        let path = &config.path;
+        let samples = if path.to_lowercase().ends_with(".wav") {
+            read_wav(path)?
+        } else {
+            let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            let pcm = bytes_to_i16(&bytes);
+            resample(
+                &downmix(&pcm, config.channels),
+                config.sample_rate,
+                SAMPLE_RATE,
+            )
+        };
+        eprintln!(
+            "[Аудио]: файл {} ({:.1} с)",
+            path,
+            samples.len() as f64 / SAMPLE_RATE as f64
+        );
+        Ok(Self {
+            samples,
+            pos: 0,
+            realtime: config.realtime,
+            started: None,
+        })
+    }
+}
+
+impl AudioSource for FileSource {
+    fn read_chunk(&mut self, ms: f64) -> Result<Option<Vec<i16>>, AudioError> {
+        if self.pos >= self.samples.len() {
+            return Ok(None);
+        }
+
+        // Pace the replay so playback and timeouts behave as with a live mic.
+        if self.realtime {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            let due = started + Duration::from_secs_f64(self.pos as f64 / SAMPLE_RATE as f64);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+        }
+
+        let n = samples_for(ms, SAMPLE_RATE);
+        let end = (self.pos + n).min(self.samples.len());
+        let mut chunk = self.samples[self.pos..end].to_vec();
+        chunk.resize(n, 0);
+        self.pos = end;
+        Ok(Some(chunk))
+    }
+}
+
+fn read_wav(path: &str) -> Result<Vec<i16>, String> {
+    let mut reader = hound::WavReader::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let spec = reader.spec();
+    let pcm: Vec<i16> = match spec.sample_format {
+        hound::SampleFormat::Int => {
+            let shift = spec.bits_per_sample as i32 - 16;
+            reader
+                .samples::<i32>()
+                .map(|s| {
+                    s.map(|v| {
+                        if shift >= 0 {
+                            (v >> shift) as i16
+                        } else {
+                            (v << -shift) as i16
+                        }
+                    })
+                })
+                .collect::<Result<_, _>>()
+        }
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|s| s.map(|v| (v.clamp(-1.0, 1.0) * i16::MAX as f32) as i16))
+            .collect::<Result<_, _>>(),
+    }
+    .map_err(|e| format!("{}: {}", path, e))?;
+
+    Ok(resample(
+        &downmix(&pcm, spec.channels),
+        spec.sample_rate,
+        SAMPLE_RATE,
+    ))
+}
+
+// ---------------------------------------------------------------------------
+// Raw s16le PCM piped through stdin
+// ---------------------------------------------------------------------------
+
+pub struct StdinSource {
+    stdin: std::io::Stdin,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl StdinSource {
+    pub fn new(config: &AudioConfig) -> Self {
+        Self {
+            stdin: std::io::stdin(),
+            sample_rate: config.sample_rate,
+            channels: config.channels,
+        }
+    }
+}
+
+impl AudioSource for StdinSource {
+    fn read_chunk(&mut self, ms: f64) -> Result<Option<Vec<i16>>, AudioError> {
+        let frames = samples_for(ms, self.sample_rate);
+        let mut bytes = vec![0u8; frames * self.channels.max(1) as usize * 2];
+        let mut filled = 0;
+        let mut lock = self.stdin.lock();
+        while filled < bytes.len() {
+            match lock.read(&mut bytes[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(AudioError::Source(format!("stdin: {}", e))),
+            }
+        }
+        if filled == 0 {
+            return Ok(None);
+        }
+
+        let pcm = bytes_to_i16(&bytes);
+        let mut chunk = resample(&downmix(&pcm, self.channels), self.sample_rate, SAMPLE_RATE);
+        chunk.resize(samples_for(ms, SAMPLE_RATE), 0);
+        Ok(Some(chunk))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PCM helpers
+// ---------------------------------------------------------------------------
+
+fn samples_for(ms: f64, rate: u32) -> usize {
+    (ms * rate as f64 / 1000.0).round() as usize
+}
+
+fn bytes_to_i16(bytes: &[u8]) -> Vec<i16> {
+    bytes
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+/// Average interleaved channels into a single mono channel.
+fn downmix(pcm: &[i16], channels: u16) -> Vec<i16> {
+    if channels <= 1 {
+        return pcm.to_vec();
+    }
+    pcm.chunks(channels as usize)
+        .map(|frame| (frame.iter().map(|&s| s as i32).sum::<i32>() / frame.len() as i32) as i16)
+        .collect()
+}
+
+/// Linear-interpolation resampler; good enough for speech recognition input.
+pub fn resample(pcm: &[i16], from: u32, to: u32) -> Vec<i16> {
+    if from == to || pcm.is_empty() {
+        return pcm.to_vec();
+    }
+    let out_len = (pcm.len() as u64 * to as u64 / from as u64) as usize;
+    let step = from as f64 / to as f64;
+    (0..out_len)
+        .map(|i| {
+            let src = i as f64 * step;
+            let idx = src as usize;
+            let frac = src - idx as f64;
+            let a = pcm[idx] as f64;
+            let b = *pcm.get(idx + 1).unwrap_or(&pcm[idx]) as f64;
+            (a + (b - a) * frac) as i16
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AssistantConfig, PersonaConfig, TimingConfig, VadConfig};
+    use crate::conversation::{Action, Conversation, Event};
+    use crate::vad::Vad;
+
+    /// A file in the temp dir, removed when dropped.
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let name = format!("voice-home-{}-{}", std::process::id(), name);
+            Self(std::env::temp_dir().join(name))
+        }
+
+        fn path(&self) -> String {
+            self.0.to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn file_config(path: String) -> AudioConfig {
+        AudioConfig {
+            source: AudioSourceKind::File,
+            path,
+            ..AudioConfig::default()
+        }
+    }
+
+    /// `secs` of a 300 Hz tone (or silence) at `rate`.
+    fn tone(rate: u32, secs: f64, loud: bool) -> Vec<i16> {
+        let amplitude = if loud { 8000.0 } else { 0.0 };
+        (0..(rate as f64 * secs) as usize)
+            .map(|i| {
+                let t = i as f64 / rate as f64;
+                (amplitude * (2.0 * std::f64::consts::PI * 300.0 * t).sin()) as i16
+            })
+            .collect()
+    }
+
+    fn write_wav(path: &str, rate: u32, channels: u16, mono: &[i16]) {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate: rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for &s in mono {
+            for _ in 0..channels {
+                writer.write_sample(s).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn downmix_averages_channels() {
+        assert_eq!(downmix(&[100, 300, -50, 50], 2), [200, 0]);
+        assert_eq!(downmix(&[1, 2, 3], 1), [1, 2, 3]);
+    }
+
+    #[test]
+    fn resample_keeps_same_rate() {
+        assert_eq!(resample(&[1, 2, 3], 16000, 16000), [1, 2, 3]);
+        assert!(resample(&[], 8000, 16000).is_empty());
+    }
+
+    #[test]
+    fn resample_interpolates_up() {
+        assert_eq!(
+            resample(&[0, 100, 200], 8000, 16000),
+            [0, 50, 100, 150, 200, 200]
+        );
+    }
+
+    #[test]
+    fn resample_decimates_down() {
+        let ramp: Vec<i16> = (0..48).collect();
+        let out = resample(&ramp, 48000, 16000);
+        assert_eq!(out.len(), 16);
+        assert_eq!(&out[..4], [0, 3, 6, 9]);
+    }
+
+    #[test]
+    fn file_source_reads_raw_pcm_at_its_rate() {
+        let file = TempFile::new("raw.pcm");
+        let pcm: Vec<u8> = [1000i16; 1600]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        std::fs::write(&file.0, pcm).unwrap();
+        let mut config = file_config(file.path());
+        config.sample_rate = 8000;
+
+        // 100 ms at 8 kHz becomes 200 ms at 16 kHz: two full chunks.
+        let mut source = FileSource::open(&config).unwrap();
+        for _ in 0..2 {
+            let chunk = source.read_chunk(100.0).unwrap().unwrap();
+            assert_eq!(chunk.len(), 1600);
+            assert!(chunk.iter().all(|&s| s == 1000));
+        }
+        assert!(source.read_chunk(100.0).unwrap().is_none());
+    }
+
+    #[test]
+    fn file_source_pads_last_chunk() {
+        let file = TempFile::new("short.wav");
+        write_wav(&file.path(), 16000, 1, &[500; 1000]);
+        let mut source = FileSource::open(&file_config(file.path())).unwrap();
+        let chunk = source.read_chunk(100.0).unwrap().unwrap();
+        assert_eq!(chunk.len(), 1600);
+        assert_eq!((chunk[999], chunk[1000]), (500, 0));
+        assert!(source.read_chunk(100.0).unwrap().is_none());
+    }
+
+    /// Replay a recorded session: silence, the wake word and a query, then
+    /// silence.  The recognizer is stood in for by a fixed transcript of the
+    /// speech, so the state machine sees what it would with Vosk.
+    #[test]
+    fn replayed_session_reaches_the_llm() {
+        let file = TempFile::new("session.wav");
+        let mut session = tone(44100, 0.5, false);
+        session.extend(tone(44100, 0.8, true));
+        session.extend(tone(44100, 1.5, false));
+        write_wav(&file.path(), 44100, 2, &session);
+
+        let assistant: AssistantConfig = toml::from_str("").unwrap();
+        let persona = PersonaConfig {
+            name: "джарвис".into(),
+            wake_word: vec!["джарвис".into()],
+            system_prompt: String::new(),
+            voice: String::new(),
+            model: String::new(),
+            tools: None,
+        };
+        let timing = TimingConfig::default();
+        let mut conversation = Conversation::new(&assistant, &[persona], &timing);
+        let mut vad = Vad::new(&VadConfig::default(), &timing);
+        let mut source = FileSource::open(&file_config(file.path())).unwrap();
+
+        let mut in_speech = false;
+        let mut actions = Vec::new();
+        while let Some(chunk) = source.read_chunk(timing.chunk_ms as f64).unwrap() {
+            let event = match (vad.is_speech(&chunk), in_speech) {
+                (true, _) => Event::Partial("джарвис который".into()),
+                (false, true) => Event::Final("джарвис который час".into()),
+                (false, false) => Event::Tick,
+            };
+            in_speech = matches!(event, Event::Partial(_));
+            actions.extend(conversation.handle(event));
+        }
+        assert!(actions.contains(&Action::Ask("который час".into())));
+    }
+}
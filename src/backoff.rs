@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+// ---------------------------------------------------------------------------
+// Backoff — exponentially growing delay between reconnection attempts
+// ---------------------------------------------------------------------------
+
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            next: initial,
+        }
+    }
+
+    /// Delay before the next attempt; doubles on every call up to `max`.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    /// Start over from the initial delay after a success.
+    pub fn reset(&mut self) {
+        self.next = self.initial;
+    }
+}
@@ -13,7 +13,344 @@ pub struct Config {
     #[serde(default)]
     pub tool: Vec<ToolConfig>,
     #[serde(default)]
+    pub tool_defaults: ToolDefaultsConfig,
+    #[serde(default)]
+    pub mcp_server: Vec<McpServerConfig>,
+    /// Assistants with their own wake words; the `[assistant]` wake word, if
+    /// any, becomes the first one when the config is loaded.
+    #[serde(default)]
+    pub persona: Vec<PersonaConfig>,
+    #[serde(default)]
     pub tts: TtsConfig,
+    #[serde(default)]
+    pub earcons: EarconsConfig,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    #[serde(default)]
+    pub aec: AecConfig,
+    #[serde(default)]
+    pub vad: VadConfig,
+    #[serde(default)]
+    pub timing: TimingConfig,
+    #[serde(default)]
+    pub context: ContextConfig,
+    #[serde(default)]
+    pub memory: MemoryConfig,
+    #[serde(default)]
+    pub timers: SchedulerConfig,
+    /// Commands answered without the LLM, tried in order.
+    #[serde(default)]
+    pub intent: Vec<IntentConfig>,
+}
+
+/// Acoustic echo cancellation between the audio source and the recognizer,
+/// using what is being played as the reference signal.
+#[derive(Deserialize)]
+pub struct AecConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Echo tail covered by the adaptive filter.
+    #[serde(default = "AecConfig::default_filter_ms")]
+    pub filter_ms: u64,
+    /// Fixed playback → microphone latency skipped before the filter.
+    #[serde(default = "AecConfig::default_delay_ms")]
+    pub delay_ms: u64,
+    /// NLMS step size (0–1): higher adapts faster but is noisier.
+    #[serde(default = "AecConfig::default_step")]
+    pub step: f32,
+}
+
+impl Default for AecConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            filter_ms: Self::default_filter_ms(),
+            delay_ms: Self::default_delay_ms(),
+            step: Self::default_step(),
+        }
+    }
+}
+
+impl AecConfig {
+    fn default_filter_ms() -> u64 {
+        64
+    }
+    fn default_delay_ms() -> u64 {
+        40
+    }
+    fn default_step() -> f32 {
+        0.3
+    }
+}
+
+/// How long the conversation outlives a wake session.
+#[derive(Deserialize, Default)]
+pub struct ContextConfig {
+    /// Minutes after the last turn during which waking the same persona
+    /// continues the conversation (0 = every session starts afresh).
+    #[serde(default, alias = "context_retention")]
+    pub retention_min: u64,
+    /// JSON file the conversation is saved to after every turn, so that it
+    /// survives a restart.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// Facts the assistant remembers through its built-in memory tools.
+#[derive(Deserialize)]
+pub struct MemoryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// JSON file holding the facts.
+    #[serde(default = "MemoryConfig::default_path")]
+    pub path: String,
+    /// How many of the most recent facts go into the system prompt of every
+    /// conversation.  They are not matched against the query, which is not
+    /// known yet; the model finds older facts through `recall_facts`.
+    #[serde(default = "MemoryConfig::default_prompt_facts")]
+    pub prompt_facts: usize,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: Self::default_path(),
+            prompt_facts: Self::default_prompt_facts(),
+        }
+    }
+}
+
+impl MemoryConfig {
+    fn default_path() -> String {
+        "memory.json".into()
+    }
+    fn default_prompt_facts() -> usize {
+        20
+    }
+}
+
+/// Sentences that call a tool and reply from a template, without the LLM.
+#[derive(Deserialize)]
+pub struct IntentConfig {
+    /// Shown in logs; the first sentence if empty.
+    #[serde(default)]
+    pub name: String,
+    /// Templates matched against the whole query, e.g. `включи {device}`;
+    /// `[words]` may be left out.
+    #[serde(deserialize_with = "one_or_many")]
+    pub sentences: Vec<String>,
+    #[serde(default)]
+    pub tool: Option<String>,
+    /// Tool argument templates; every slot by its name if empty.
+    #[serde(default)]
+    pub args: HashMap<String, String>,
+    /// Spoken reply template; `{result}` is the tool's output.
+    #[serde(default = "IntentConfig::default_reply")]
+    pub reply: String,
+    /// Slots not listed here match any text.
+    #[serde(default)]
+    pub slots: HashMap<String, SlotConfig>,
+}
+
+impl IntentConfig {
+    fn default_reply() -> String {
+        "Готово.".into()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SlotConfig {
+    #[serde(default)]
+    pub kind: SlotKind,
+    /// Spoken form → value passed to the tool (`list` slots).
+    #[serde(default)]
+    pub values: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotKind {
+    /// One of `values`.
+    #[default]
+    List,
+    /// 0–999 in digits or words.
+    Number,
+    /// A color name, as `#rrggbb` or its `.red`, `.green`, `.blue` parts.
+    Color,
+    /// Any words.
+    Text,
+}
+
+/// Timers and reminders set through the built-in tools.
+#[derive(Deserialize)]
+pub struct SchedulerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// JSON file holding the pending timers and reminders.
+    #[serde(default = "SchedulerConfig::default_path")]
+    pub path: String,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: Self::default_path(),
+        }
+    }
+}
+
+impl SchedulerConfig {
+    fn default_path() -> String {
+        "timers.json".into()
+    }
+}
+
+/// Voice activity detection in front of the recognizer.
+#[derive(Deserialize)]
+pub struct VadConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How far above the tracked noise floor a frame must be to count as speech.
+    #[serde(default = "VadConfig::default_threshold_db")]
+    pub threshold_db: f32,
+    /// Keep treating audio as speech this long after it drops below the threshold.
+    #[serde(default = "VadConfig::default_hangover_ms")]
+    pub hangover_ms: u64,
+    /// Do not feed silence to Vosk while waiting for the wake word.
+    #[serde(default = "VadConfig::default_skip_idle_silence")]
+    pub skip_idle_silence: bool,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: Self::default_threshold_db(),
+            hangover_ms: Self::default_hangover_ms(),
+            skip_idle_silence: Self::default_skip_idle_silence(),
+        }
+    }
+}
+
+impl VadConfig {
+    fn default_threshold_db() -> f32 {
+        9.0
+    }
+    fn default_hangover_ms() -> u64 {
+        300
+    }
+    fn default_skip_idle_silence() -> bool {
+        true
+    }
+}
+
+/// Turn-taking timeouts, all in milliseconds.
+#[derive(Deserialize)]
+pub struct TimingConfig {
+    /// Audio read per recognizer step; the other values are rounded up to it.
+    #[serde(default = "TimingConfig::default_chunk_ms")]
+    pub chunk_ms: u64,
+    /// Silence after a finished phrase before the query is sent, so that
+    /// multi-sentence queries are not split.
+    #[serde(default = "TimingConfig::default_grace_ms")]
+    pub grace_ms: u64,
+    /// Silence after the wake word before returning to idle.
+    #[serde(default = "TimingConfig::default_wake_timeout_ms")]
+    pub wake_timeout_ms: u64,
+    /// Silence after a reply during which a follow-up needs no wake word.
+    #[serde(default = "TimingConfig::default_follow_up_ms")]
+    pub follow_up_ms: u64,
+    /// Longest query before it is sent regardless of pauses (0 = no limit).
+    #[serde(default = "TimingConfig::default_max_query_ms")]
+    pub max_query_ms: u64,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            chunk_ms: Self::default_chunk_ms(),
+            grace_ms: Self::default_grace_ms(),
+            wake_timeout_ms: Self::default_wake_timeout_ms(),
+            follow_up_ms: Self::default_follow_up_ms(),
+            max_query_ms: Self::default_max_query_ms(),
+        }
+    }
+}
+
+impl TimingConfig {
+    /// Number of chunks covering `ms`, at least one.
+    pub fn chunks(&self, ms: u64) -> u32 {
+        ms.div_ceil(self.chunk_ms.max(1)).max(1) as u32
+    }
+
+    fn default_chunk_ms() -> u64 {
+        100
+    }
+    fn default_grace_ms() -> u64 {
+        300
+    }
+    fn default_wake_timeout_ms() -> u64 {
+        2000
+    }
+    fn default_follow_up_ms() -> u64 {
+        2000
+    }
+    fn default_max_query_ms() -> u64 {
+        15000
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioSourceKind {
+    /// PulseAudio microphone via `parec`.
+    #[default]
+    Parec,
+    /// Recorded WAV or raw s16le file.
+    File,
+    /// Raw s16le PCM piped through stdin.
+    Stdin,
+}
+
+#[derive(Deserialize)]
+pub struct AudioConfig {
+    #[serde(default)]
+    pub source: AudioSourceKind,
+    /// Path of the file to replay when `source = "file"`.
+    #[serde(default)]
+    pub path: String,
+    /// Sample rate of raw PCM input (WAV files carry their own).
+    #[serde(default = "AudioConfig::default_sample_rate")]
+    pub sample_rate: u32,
+    /// Channel count of raw PCM input (WAV files carry their own).
+    #[serde(default = "AudioConfig::default_channels")]
+    pub channels: u16,
+    /// Replay files at real-time speed instead of as fast as possible.
+    #[serde(default)]
+    pub realtime: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            source: AudioSourceKind::default(),
+            path: String::new(),
+            sample_rate: Self::default_sample_rate(),
+            channels: Self::default_channels(),
+            realtime: false,
+        }
+    }
+}
+
+impl AudioConfig {
+    fn default_sample_rate() -> u32 {
+        16000
+    }
+    fn default_channels() -> u16 {
+        1
+    }
 }
 
 #[derive(Deserialize)]
@@ -36,16 +373,70 @@ impl TtsConfig {
     }
 }
 
+/// Short sounds confirming state changes.  Each cue is `"tone"` for the
+/// built-in sound, a path to an audio file, or `""` for none.
+#[derive(Deserialize)]
+pub struct EarconsConfig {
+    /// Wake word heard (or playback interrupted), listening for the query.
+    #[serde(default)]
+    pub wake: String,
+    /// Query sent to the LLM.
+    #[serde(default)]
+    pub query: String,
+    /// Back to waiting for the wake word.
+    #[serde(default)]
+    pub idle: String,
+    /// Reply finished, listening for a follow-up.
+    #[serde(default)]
+    pub follow_up: String,
+    /// Something went wrong, e.g. the LLM is unreachable.
+    #[serde(default)]
+    pub error: String,
+    /// A timer or reminder is due; played in full before the announcement.
+    #[serde(default = "EarconsConfig::default_alarm")]
+    pub alarm: String,
+    #[serde(default = "EarconsConfig::default_volume")]
+    pub volume: f32,
+}
+
+impl Default for EarconsConfig {
+    fn default() -> Self {
+        Self {
+            wake: String::new(),
+            query: String::new(),
+            idle: String::new(),
+            follow_up: String::new(),
+            error: String::new(),
+            alarm: Self::default_alarm(),
+            volume: Self::default_volume(),
+        }
+    }
+}
+
+impl EarconsConfig {
+    fn default_alarm() -> String {
+        "tone".into()
+    }
+    fn default_volume() -> f32 {
+        0.5
+    }
+}
+
 #[derive(Deserialize)]
 pub struct VoskConfig {
     #[serde(default = "VoskConfig::default_model_path")]
     pub model_path: String,
+    /// Spot the wake word with a small grammar recognizer while idle and
+    /// run the full model only on utterances that may contain it.
+    #[serde(default)]
+    pub idle_grammar: bool,
 }
 
 impl Default for VoskConfig {
     fn default() -> Self {
         Self {
             model_path: Self::default_model_path(),
+            idle_grammar: false,
         }
     }
 }
@@ -56,16 +447,45 @@ impl VoskConfig {
     }
 }
 
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BargeIn {
+    /// Only stop words interrupt playback.
+    #[default]
+    Off,
+    /// The wake word interrupts playback and starts a new query.
+    WakeWord,
+    /// Any final result with at least `barge_in_min_words` words does.
+    Speech,
+}
+
 #[derive(Deserialize)]
 pub struct AssistantConfig {
-    pub wake_word: String,
+    /// One wake word or a list of accepted variants (may be left out when
+    /// `[[persona]]` entries are defined).
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub wake_word: Vec<String>,
+    /// Minimum similarity (0–1) between a variant and the heard words;
+    /// below 1 tolerates misrecognized letters.
+    #[serde(default = "AssistantConfig::default_wake_similarity")]
+    pub wake_similarity: f32,
+    /// Compare wake words by approximate Russian pronunciation.
+    #[serde(default)]
+    pub wake_phonetic: bool,
     #[serde(default = "AssistantConfig::default_stop_words")]
     pub stop_words: Vec<String>,
     #[serde(default = "AssistantConfig::default_system_prompt")]
     pub system_prompt: String,
+    #[serde(default)]
+    pub barge_in: BargeIn,
+    #[serde(default = "AssistantConfig::default_barge_in_min_words")]
+    pub barge_in_min_words: usize,
 }
 
 impl AssistantConfig {
+    fn default_wake_similarity() -> f32 {
+        1.0
+    }
     fn default_stop_words() -> Vec<String> {
         vec![
             "стоп".into(),
@@ -77,18 +497,115 @@ impl AssistantConfig {
     fn default_system_prompt() -> String {
         "Ты голосовой ассистент. Отвечай кратко на русском языке.".into()
     }
+    fn default_barge_in_min_words() -> usize {
+        2
+    }
+}
+
+/// An assistant woken by its own name, with its own prompt, voice, model
+/// and tools.  Empty fields fall back to `[assistant]`, `[tts]` and `[openai]`.
+#[derive(Deserialize, Clone)]
+pub struct PersonaConfig {
+    #[serde(default)]
+    pub name: String,
+    #[serde(deserialize_with = "one_or_many")]
+    pub wake_word: Vec<String>,
+    #[serde(default)]
+    pub system_prompt: String,
+    /// Piper voice (`.onnx.json`).
+    #[serde(default)]
+    pub voice: String,
+    /// LLM model.
+    #[serde(default)]
+    pub model: String,
+    /// Names of the tools this persona may use; all tools if not set.
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+}
+
+impl PersonaConfig {
+    pub fn allows_tool(&self, name: &str) -> bool {
+        self.tools
+            .as_ref()
+            .is_none_or(|tools| tools.iter().any(|t| t == name))
+    }
 }
 
 #[derive(Deserialize)]
 pub struct OpenAiConfig {
     #[serde(default = "OpenAiConfig::default_model")]
     pub model: String,
+    /// OpenAI-compatible endpoint, e.g. `http://192.168.1.10:11434/v1` for Ollama.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Environment variable holding the API key.
+    #[serde(default = "OpenAiConfig::default_api_key_env")]
+    pub api_key_env: String,
+    /// File holding the API key; takes precedence over `api_key_env`.
+    #[serde(default)]
+    pub api_key_file: Option<String>,
+    #[serde(default)]
+    pub organization: Option<String>,
+    /// Extra HTTP headers sent with every request.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Stream the reply and speak it sentence by sentence as it arrives.
+    #[serde(default)]
+    pub stream: bool,
+    /// Longest wait for a response (or for the next piece of a streamed one).
+    #[serde(default = "OpenAiConfig::default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Extra attempts per endpoint after network errors and timeouts.
+    #[serde(default = "OpenAiConfig::default_retries")]
+    pub retries: u32,
+    /// Delay before the first retry; doubles with every further one.
+    #[serde(default = "OpenAiConfig::default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    /// Spoken when no endpoint could answer; empty for the error cue only.
+    #[serde(default = "OpenAiConfig::default_apology")]
+    pub apology: String,
+    /// Endpoints tried in order when this one keeps failing.
+    #[serde(default)]
+    pub fallback: Vec<FallbackConfig>,
+    /// Replies with tool calls per query before an answer is demanded
+    /// without offering tools (0 = tools are never offered).
+    #[serde(default = "OpenAiConfig::default_max_tool_rounds")]
+    pub max_tool_rounds: u32,
+    /// Tool calls run from a single reply; further ones are refused and an
+    /// answer is demanded.
+    #[serde(default = "OpenAiConfig::default_max_tool_calls_per_turn")]
+    pub max_tool_calls_per_turn: usize,
+    /// Conversation messages sent with a query (0 = no limit).
+    #[serde(default = "OpenAiConfig::default_max_history_messages")]
+    pub max_history_messages: usize,
+    /// Estimated tokens sent with a query (0 = no limit).
+    #[serde(default = "OpenAiConfig::default_max_history_tokens")]
+    pub max_history_tokens: usize,
+    /// What happens to the oldest turns when the history is over budget.
+    #[serde(default)]
+    pub history_strategy: HistoryStrategy,
 }
 
 impl Default for OpenAiConfig {
     fn default() -> Self {
         Self {
             model: Self::default_model(),
+            base_url: None,
+            api_key_env: Self::default_api_key_env(),
+            api_key_file: None,
+            organization: None,
+            headers: HashMap::new(),
+            stream: false,
+            timeout_ms: Self::default_timeout_ms(),
+            retries: Self::default_retries(),
+            retry_delay_ms: Self::default_retry_delay_ms(),
+            apology: Self::default_apology(),
+            fallback: Vec::new(),
+            max_tool_rounds: Self::default_max_tool_rounds(),
+            max_tool_calls_per_turn: Self::default_max_tool_calls_per_turn(),
+            max_history_messages: Self::default_max_history_messages(),
+            max_history_tokens: Self::default_max_history_tokens(),
+            history_strategy: HistoryStrategy::default(),
         }
     }
 }
@@ -97,6 +614,60 @@ impl OpenAiConfig {
     fn default_model() -> String {
         "gpt-4o-mini".into()
     }
+    fn default_api_key_env() -> String {
+        "OPENAI_API_KEY".into()
+    }
+    fn default_timeout_ms() -> u64 {
+        30000
+    }
+    fn default_retries() -> u32 {
+        2
+    }
+    fn default_retry_delay_ms() -> u64 {
+        500
+    }
+    fn default_apology() -> String {
+        "Извините, не могу сейчас ответить.".into()
+    }
+    fn default_max_tool_rounds() -> u32 {
+        5
+    }
+    fn default_max_tool_calls_per_turn() -> usize {
+        8
+    }
+    fn default_max_history_messages() -> usize {
+        40
+    }
+    fn default_max_history_tokens() -> usize {
+        6000
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryStrategy {
+    /// Forget the oldest turns.
+    #[default]
+    Drop,
+    /// Replace the oldest turns with a short summary written by the model.
+    Summarize,
+}
+
+/// Another model and/or endpoint to try; unset fields are taken from `[openai]`.
+#[derive(Deserialize)]
+pub struct FallbackConfig {
+    pub model: String,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub api_key_file: Option<String>,
+    #[serde(default)]
+    pub organization: Option<String>,
+    /// Replaces the `[openai]` headers when set.
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
 }
 
 #[derive(Deserialize)]
@@ -125,15 +696,101 @@ impl TimeRangeConfig {
     }
 }
 
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolKind {
+    /// Run `command` / `argv` as a child process.
+    #[default]
+    Command,
+    /// Send an HTTP request described by `method`, `url`, `headers` and `body`.
+    Http,
+}
+
 #[derive(Deserialize, Clone)]
 pub struct ToolConfig {
     pub name: String,
     pub description: String,
+    #[serde(default)]
+    pub kind: ToolKind,
+    /// Shell command template run via `sh -c`; arguments are shell-quoted.
+    #[serde(default)]
     pub command: String,
+    /// Program and arguments executed directly, without a shell.
+    /// Takes precedence over `command` when non-empty.
+    #[serde(default)]
+    pub argv: Vec<String>,
+    #[serde(default = "ToolConfig::default_method")]
+    pub method: String,
+    /// URL template; arguments are percent-encoded.
+    #[serde(default)]
+    pub url: String,
+    /// Header templates; arguments are inserted as is.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// JSON body template; each `{{param}}` is replaced by the argument as a
+    /// JSON value, so placeholders must not be quoted.
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Status codes treated as success; any 2xx when empty.
+    #[serde(default)]
+    pub expect_status: Vec<u16>,
+    /// JSONPath-style expression (e.g. `$.state.is_enabled`) selecting the
+    /// part of a JSON response returned to the model.
+    #[serde(default)]
+    pub extract: Option<String>,
     #[serde(default)]
     pub params: HashMap<String, ParamConfig>,
     #[serde(default)]
     pub required_params: Vec<String>,
+    /// Overrides `[tool_defaults]` for this tool.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub max_output_bytes: Option<usize>,
+    #[serde(default)]
+    pub kill_on_timeout: Option<bool>,
+    /// May run at the same time as other calls from the same reply.
+    #[serde(default)]
+    pub parallel_safe: bool,
+}
+
+impl ToolConfig {
+    fn default_method() -> String {
+        "GET".into()
+    }
+}
+
+/// Limits applied to every `[[tool]]` that does not set its own.
+#[derive(Deserialize, Clone)]
+pub struct ToolDefaultsConfig {
+    #[serde(default = "ToolDefaultsConfig::default_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "ToolDefaultsConfig::default_max_output_bytes")]
+    pub max_output_bytes: usize,
+    #[serde(default = "ToolDefaultsConfig::default_kill_on_timeout")]
+    pub kill_on_timeout: bool,
+}
+
+impl Default for ToolDefaultsConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: Self::default_timeout_ms(),
+            max_output_bytes: Self::default_max_output_bytes(),
+            kill_on_timeout: Self::default_kill_on_timeout(),
+        }
+    }
+}
+
+impl ToolDefaultsConfig {
+    fn default_timeout_ms() -> u64 {
+        10_000
+    }
+    fn default_max_output_bytes() -> usize {
+        4096
+    }
+    fn default_kill_on_timeout() -> bool {
+        true
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -142,11 +799,135 @@ pub struct ParamConfig {
     pub param_type: String,
     #[serde(default)]
     pub description: String,
+    /// Substitute the value into `command` verbatim, without shell quoting.
+    /// Only for trusted, already-validated values.
+    #[serde(default)]
+    pub raw: bool,
+    /// Allowed values.
+    #[serde(default, rename = "enum")]
+    pub allowed: Vec<serde_json::Value>,
+    /// Inclusive bounds for `integer` / `number` params.
+    #[serde(default)]
+    pub minimum: Option<f64>,
+    #[serde(default)]
+    pub maximum: Option<f64>,
+    /// Regular expression a `string` value must match in full.
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// `pattern` compiled, set when the tools are loaded.
+    #[serde(skip)]
+    pub regex: Option<regex::Regex>,
+}
+
+/// A Model Context Protocol server spawned as a child process and spoken to
+/// over stdio; its tools are offered to the model next to `[[tool]]` ones.
+#[derive(Deserialize, Clone)]
+pub struct McpServerConfig {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// How long to wait for any single request.
+    #[serde(default = "McpServerConfig::default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl McpServerConfig {
+    fn default_timeout_ms() -> u64 {
+        10_000
+    }
 }
 
 impl Config {
     pub fn load(path: &str) -> Result<Self, String> {
         let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
-        toml::from_str(&content).map_err(|e| format!("{}: {}", path, e))
+        let mut config: Self = toml::from_str(&content).map_err(|e| format!("{}: {}", path, e))?;
+        if config.timing.chunk_ms == 0 {
+            return Err(format!("{}: timing.chunk_ms должен быть больше нуля", path));
+        }
+
+        // -- the [assistant] wake word is the default persona --
+        config.assistant.wake_word.retain(|w| !w.trim().is_empty());
+        if !config.assistant.wake_word.is_empty() {
+            config.persona.insert(
+                0,
+                PersonaConfig {
+                    name: String::new(),
+                    wake_word: config.assistant.wake_word.clone(),
+                    system_prompt: String::new(),
+                    voice: String::new(),
+                    model: String::new(),
+                    tools: None,
+                },
+            );
+        }
+        if config.persona.is_empty() {
+            return Err(format!(
+                "{}: не задано ни assistant.wake_word, ни [[persona]]",
+                path
+            ));
+        }
+        for persona in &mut config.persona {
+            persona.wake_word.retain(|w| !w.trim().is_empty());
+            if persona.wake_word.is_empty() {
+                return Err(format!(
+                    "{}: у персоны «{}» нет wake_word",
+                    path, persona.name
+                ));
+            }
+            if persona.name.is_empty() {
+                persona.name = persona.wake_word[0].clone();
+            }
+            if persona.system_prompt.is_empty() {
+                persona.system_prompt = config.assistant.system_prompt.clone();
+            }
+            if persona.voice.is_empty() {
+                persona.voice = config.tts.model_path.clone();
+            }
+            if persona.model.is_empty() {
+                persona.model = config.openai.model.clone();
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Accept either `key = "value"` or `key = ["a", "b"]`.
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(toml: &str) -> Result<Config, String> {
+        let path =
+            std::env::temp_dir().join(format!("voice-home-config-{}.toml", std::process::id()));
+        std::fs::write(&path, toml).unwrap();
+        let config = Config::load(path.to_str().unwrap());
+        let _ = std::fs::remove_file(&path);
+        config
+    }
+
+    #[test]
+    fn rejects_zero_chunk() {
+        let base = "[assistant]\nwake_word = \"джарвис\"\n";
+        assert!(load(base).is_ok());
+        let err = load(&format!("{}[timing]\nchunk_ms = 0\n", base))
+            .err()
+            .unwrap();
+        assert!(err.contains("chunk_ms"), "{err}");
     }
 }
@@ -0,0 +1,203 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ContextConfig, PersonaConfig};
+use crate::openai::{self, Message};
+
+// ---------------------------------------------------------------------------
+// Context — the conversation history, kept across wake sessions
+// ---------------------------------------------------------------------------
+
+/// Conversation history that survives going idle for `retention_min`
+/// after the last turn and, with `path` set, restarts of the daemon.
+pub struct Context {
+    retention: Duration,
+    path: Option<String>,
+    saved: Saved,
+}
+
+/// On-disk form of the context.
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    /// Name of the persona the conversation is held with.
+    persona: String,
+    /// Unix time of the last turn; 0 before the first one.
+    updated: u64,
+    messages: Vec<Message>,
+}
+
+impl Context {
+    /// Start with the saved conversation if it is recent enough and held
+    /// with `persona`, otherwise with a fresh one.  `system_prompt` is the
+    /// persona's prompt as sent, e.g. with remembered facts added.
+    pub fn load(config: &ContextConfig, persona: &PersonaConfig, system_prompt: &str) -> Self {
+        let mut context = Self {
+            retention: Duration::from_secs(config.retention_min * 60),
+            path: config.path.clone(),
+            saved: Saved {
+                persona: persona.name.clone(),
+                updated: 0,
+                messages: openai::initial_history(system_prompt),
+            },
+        };
+        let Some(path) = &config.path else {
+            return context;
+        };
+        let saved = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str::<Saved>(&json).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return context,
+            Err(e) => Err(e.to_string()),
+        };
+        match saved {
+            Ok(saved) => {
+                let fresh = std::mem::replace(&mut context.saved, saved);
+                if context.resume(persona, system_prompt) {
+                    eprintln!(
+                        "[Система]: Разговор продолжается ({} сообщений).",
+                        context.saved.messages.len() - 1
+                    );
+                } else {
+                    context.saved = fresh;
+                }
+            }
+            Err(e) => eprintln!("[Система]: Контекст не загружен: {}: {}", path, e),
+        }
+        context
+    }
+
+    pub fn history_mut(&mut self) -> &mut Vec<Message> {
+        &mut self.saved.messages
+    }
+
+    /// Begin a session with `persona`: the previous conversation goes on if
+    /// its last turn is within the retention window, else a new one starts.
+    pub fn start(&mut self, persona: &PersonaConfig, system_prompt: &str) {
+        if !self.resume(persona, system_prompt) {
+            self.saved = Saved {
+                persona: persona.name.clone(),
+                updated: 0,
+                messages: openai::initial_history(system_prompt),
+            };
+        }
+    }
+
+    /// Record that a turn has just ended and save the history.
+    pub fn touch(&mut self) {
+        self.saved.updated = unix_now();
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_string(&self.saved)
+            .map_err(|e| e.to_string())
+            .and_then(|json| crate::persist::write_atomic(path, &json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("[Система]: Контекст не сохранён: {}: {}", path, e);
+        }
+    }
+
+    /// Keep the current conversation for `persona` if it is still recent,
+    /// with the system prompt brought up to date.
+    fn resume(&mut self, persona: &PersonaConfig, system_prompt: &str) -> bool {
+        let age = Duration::from_secs(unix_now().saturating_sub(self.saved.updated));
+        if self.saved.updated == 0
+            || self.saved.persona != persona.name
+            || age >= self.retention
+            || self.saved.messages.is_empty()
+        {
+            return false;
+        }
+        self.saved.messages[0] = openai::system_message(system_prompt);
+        true
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn persona(name: &str) -> PersonaConfig {
+        PersonaConfig {
+            name: name.into(),
+            wake_word: vec![name.into()],
+            system_prompt: String::new(),
+            voice: String::new(),
+            model: String::new(),
+            tools: None,
+        }
+    }
+
+    fn context(config: &str) -> Context {
+        let config: ContextConfig = toml::from_str(config).unwrap();
+        Context::load(&config, &persona("джарвис"), "prompt")
+    }
+
+    /// One finished exchange, `minutes_ago` old.
+    fn talk(context: &mut Context, minutes_ago: u64) {
+        let history = context.history_mut();
+        history.push(openai::user_message("который час"));
+        history.push(openai::assistant_message("десять"));
+        context.touch();
+        context.saved.updated -= minutes_ago * 60;
+    }
+
+    #[test]
+    fn retention_accepts_both_names() {
+        assert_eq!(
+            context("retention_min = 10").retention,
+            Duration::from_secs(600)
+        );
+        assert_eq!(
+            context("context_retention = 10").retention,
+            Duration::from_secs(600)
+        );
+        assert_eq!(context("").retention, Duration::ZERO);
+    }
+
+    #[test]
+    fn recent_conversation_goes_on_with_new_prompt() {
+        let mut c = context("retention_min = 10");
+        talk(&mut c, 9);
+        c.start(&persona("джарвис"), "new prompt");
+        assert_eq!(c.history_mut().len(), 3);
+        assert_eq!(c.history_mut()[0], openai::system_message("new prompt"));
+    }
+
+    #[test]
+    fn old_conversation_starts_afresh() {
+        let mut c = context("retention_min = 10");
+        talk(&mut c, 10);
+        c.start(&persona("джарвис"), "prompt");
+        assert_eq!(c.history_mut().len(), 1);
+    }
+
+    #[test]
+    fn other_persona_or_no_retention_starts_afresh() {
+        let mut c = context("retention_min = 10");
+        talk(&mut c, 0);
+        c.start(&persona("алиса"), "prompt");
+        assert_eq!(c.history_mut().len(), 1);
+
+        let mut c = context("");
+        talk(&mut c, 0);
+        c.start(&persona("джарвис"), "prompt");
+        assert_eq!(c.history_mut().len(), 1);
+    }
+
+    #[test]
+    fn saved_conversation_survives_a_restart() {
+        let path =
+            std::env::temp_dir().join(format!("voice-home-context-{}.json", std::process::id()));
+        let config = format!("retention_min = 10\npath = {:?}", path.to_str().unwrap());
+        let mut c = context(&config);
+        talk(&mut c, 0);
+        assert_eq!(context(&config).history_mut().len(), 3);
+        let _ = std::fs::remove_file(&path);
+    }
+}
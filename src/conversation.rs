@@ -0,0 +1,714 @@
+use crate::config::{AssistantConfig, BargeIn, PersonaConfig, TimingConfig};
+use crate::wake::WakeMatcher;
+
+// ---------------------------------------------------------------------------
+// Events in, actions out
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum State {
+    Idle,
+    ListeningQuery,
+    Speaking,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Event {
+    /// In-progress recognizer result for the current audio chunk.
+    Partial(String),
+    /// Finalized recognizer result (may be empty at end of utterance).
+    Final(String),
+    /// An audio chunk elapsed without speech (voice activity detection).
+    Tick,
+    /// The LLM replied to the last `Action::Ask`.
+    Answer(String),
+    /// Playback of the current reply has finished.
+    PlaybackFinished,
+    /// The last `Action::Ask` produced no reply.
+    Failed,
+    /// A timer or reminder is due; the text announces it.
+    Alarm(String),
+}
+
+/// Audible confirmation of a state change.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Cue {
+    Wake,
+    Query,
+    Idle,
+    FollowUp,
+    Error,
+    Alarm,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Action {
+    /// Answer as the given persona (index into `Config::persona`) from now on.
+    SelectPersona(usize),
+    /// Drop the recognizer so the next chunk starts from a clean state.
+    ResetRecognizer,
+    /// Start a fresh conversation history from the system prompt.
+    ResetHistory,
+    /// Send the query to the LLM and report the reply as `Event::Answer`.
+    Ask(String),
+    /// Start speaking the given text.
+    Speak(String),
+    /// Speak the given text unprompted and add it to the history as a
+    /// reply, so that the user can respond to it.
+    Announce(String),
+    /// Cancel the current playback.
+    StopPlayback,
+    /// Cut the latest assistant reply in the history down to what was
+    /// actually heard (must run before `StopPlayback`).
+    TruncateReply,
+    /// Play a short sound without waiting for it.
+    Cue(Cue),
+    /// Write a line to the console log.
+    Log(String),
+}
+
+// ---------------------------------------------------------------------------
+// Conversation — Idle → ListeningQuery → Speaking → ListeningQuery → …
+// ---------------------------------------------------------------------------
+
+/// The assistant's turn-taking logic, free of any audio, model or network
+/// dependencies.  Feed it one `Event` at a time and carry out the returned
+/// actions in order.
+pub struct Conversation {
+    wake: WakeMatcher,
+    stop_words: Vec<String>,
+    barge_in: BargeIn,
+    barge_in_min_words: usize,
+    /// Silence after a final result before the query is sent.
+    continuation_chunks: u32,
+    /// Silence after the wake word or a barge-in before going idle.
+    wake_timeout_chunks: u32,
+    /// Silence after a reply before going idle.
+    follow_up_chunks: u32,
+    /// Longest query before it is sent regardless of silence (0 = no limit).
+    max_query_chunks: u32,
+    state: State,
+    /// Persona whose wake word was heard last.
+    persona: usize,
+    accumulated_text: String,
+    /// Latest non-final text, sent along if the query runs too long.
+    partial_text: String,
+    silence_counter: u32,
+    /// Silence allowed in the current `ListeningQuery` before going idle.
+    idle_after: u32,
+    /// Chunks since the user started speaking the current query.
+    query_chunks: u32,
+}
+
+impl Conversation {
+    pub fn new(
+        config: &AssistantConfig,
+        personas: &[PersonaConfig],
+        timing: &TimingConfig,
+    ) -> Self {
+        Self {
+            wake: WakeMatcher::new(personas, config),
+            stop_words: config.stop_words.clone(),
+            barge_in: config.barge_in,
+            barge_in_min_words: config.barge_in_min_words,
+            continuation_chunks: timing.chunks(timing.grace_ms),
+            wake_timeout_chunks: timing.chunks(timing.wake_timeout_ms),
+            follow_up_chunks: timing.chunks(timing.follow_up_ms),
+            max_query_chunks: match timing.max_query_ms {
+                0 => 0,
+                ms => timing.chunks(ms),
+            },
+            state: State::Idle,
+            persona: 0,
+            accumulated_text: String::new(),
+            partial_text: String::new(),
+            silence_counter: 0,
+            idle_after: 0,
+            query_chunks: 0,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Return to `Idle` without emitting any actions (e.g. outside the active time range).
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.accumulated_text.clear();
+        self.partial_text.clear();
+        self.silence_counter = 0;
+        self.query_chunks = 0;
+    }
+
+    pub fn handle(&mut self, event: Event) -> Vec<Action> {
+        match self.state {
+            State::Idle => self.handle_idle(event),
+            State::ListeningQuery => self.handle_listening(event),
+            State::Speaking => self.handle_speaking(event),
+        }
+    }
+
+    // ------------------------------------------------------------------
+    // Per-state handlers
+    // ------------------------------------------------------------------
+
+    fn handle_idle(&mut self, event: Event) -> Vec<Action> {
+        // -- timer or reminder → announce it, then listen for a response --
+        if let Event::Alarm(text) = event {
+            self.state = State::Speaking;
+            return vec![
+                log(format!("[Система]: {}", text)),
+                Action::ResetHistory,
+                Action::Cue(Cue::Alarm),
+                Action::Announce(text),
+                Action::ResetRecognizer,
+            ];
+        }
+
+        let Event::Final(text) = event else {
+            return vec![];
+        };
+        let Some(wake) = self.wake.find(&text) else {
+            return vec![];
+        };
+
+        let found = log(format!(
+            "[Система]: Слово активации «{}» ~ «{}» ({:.2}).",
+            wake.variant, wake.heard, wake.score
+        ));
+        self.listen(wake.remainder, self.wake_timeout_chunks);
+        self.persona = wake.persona;
+        vec![
+            found,
+            Action::Cue(Cue::Wake),
+            Action::SelectPersona(wake.persona),
+            Action::ResetHistory,
+            Action::ResetRecognizer,
+        ]
+    }
+
+    fn handle_listening(&mut self, event: Event) -> Vec<Action> {
+        let (text, is_final) = match event {
+            Event::Partial(text) => (text, false),
+            Event::Final(text) => (text, true),
+            Event::Tick => (String::new(), false),
+            Event::Answer(_) | Event::PlaybackFinished | Event::Failed | Event::Alarm(_) => {
+                return vec![];
+            }
+        };
+
+        // -- stop word → immediately back to idle --
+        if self.has_stop_word(&text) {
+            self.reset();
+            return vec![
+                log("[Система]: Обнаружено стоп-слово, возврат в режим ожидания."),
+                Action::Cue(Cue::Idle),
+                Action::ResetHistory,
+                Action::ResetRecognizer,
+            ];
+        }
+
+        // -- another persona's wake word → hand the query over to it --
+        let mut actions = vec![];
+        let mut text = text;
+        if is_final
+            && let Some(wake) = self.wake.find(&text)
+            && wake.persona != self.persona
+        {
+            actions = self.select_persona(wake.persona);
+            self.accumulated_text.clear();
+            text = wake.remainder;
+            if text.is_empty() {
+                // Only the wake word: wait for the query like after a fresh wake.
+                self.silence_counter = 0;
+                self.idle_after = self.wake_timeout_chunks;
+                return actions;
+            }
+        }
+
+        // -- accumulate finalized text, track silence --
+        if is_final && !text.is_empty() {
+            if !self.accumulated_text.is_empty() {
+                self.accumulated_text.push(' ');
+            }
+            self.accumulated_text.push_str(&text);
+            self.partial_text.clear();
+            self.silence_counter = 0;
+        } else if is_final && text.is_empty() && !self.accumulated_text.is_empty() {
+            // Empty final after we have text — Vosk detected end of utterance.
+            // Brief grace period then send to OpenAI.
+            self.silence_counter += self.continuation_chunks;
+        } else if text.is_empty() {
+            self.silence_counter += 1;
+        } else {
+            // non-empty partial → user is still speaking
+            self.partial_text = text;
+            self.silence_counter = 0;
+        }
+
+        // -- query running too long → send what has been heard so far --
+        if !self.accumulated_text.is_empty() || !self.partial_text.is_empty() {
+            self.query_chunks += 1;
+        }
+        if self.max_query_chunks > 0 && self.query_chunks >= self.max_query_chunks {
+            let partial = std::mem::take(&mut self.partial_text);
+            if !partial.is_empty() {
+                if !self.accumulated_text.is_empty() {
+                    self.accumulated_text.push(' ');
+                }
+                self.accumulated_text.push_str(&partial);
+            }
+            actions.push(log("[Система]: Достигнута максимальная длина запроса."));
+            self.silence_counter = self.continuation_chunks;
+        }
+
+        // -- have accumulated text & grace period elapsed → ask the LLM --
+        if !self.accumulated_text.is_empty() && self.silence_counter >= self.continuation_chunks {
+            let query = std::mem::take(&mut self.accumulated_text);
+            self.state = State::Speaking;
+            self.partial_text.clear();
+            self.silence_counter = 0;
+            self.query_chunks = 0;
+            actions.extend([
+                log(format!("[Вы]: {}", query)),
+                Action::Cue(Cue::Query),
+                Action::Ask(query),
+                Action::ResetRecognizer,
+            ]);
+            return actions;
+        }
+
+        // -- silence with no pending text → go idle --
+        if self.accumulated_text.is_empty() && self.silence_counter >= self.idle_after {
+            self.reset();
+            return vec![
+                log("[Система]: Режим ожидания."),
+                Action::Cue(Cue::Idle),
+                Action::ResetHistory,
+                Action::ResetRecognizer,
+            ];
+        }
+
+        actions
+    }
+
+    fn handle_speaking(&mut self, event: Event) -> Vec<Action> {
+        match event {
+            Event::Answer(text) => vec![Action::Speak(text)],
+
+            // -- playback finished → back to listening for follow-up --
+            Event::PlaybackFinished => {
+                self.listen(String::new(), self.follow_up_chunks);
+                vec![Action::Cue(Cue::FollowUp), Action::ResetRecognizer]
+            }
+
+            // -- no reply → let the user try again without the wake word --
+            Event::Failed => {
+                self.listen(String::new(), self.follow_up_chunks);
+                vec![Action::ResetRecognizer]
+            }
+
+            // -- stop word → cancel playback, go idle --
+            Event::Partial(text) | Event::Final(text) if self.has_stop_word(&text) => {
+                self.reset();
+                vec![
+                    log("[Система]: Обнаружено стоп-слово, остановка воспроизведения."),
+                    Action::StopPlayback,
+                    Action::Cue(Cue::Idle),
+                    Action::ResetHistory,
+                    Action::ResetRecognizer,
+                ]
+            }
+
+            // -- barge-in → cut the reply short, listen to the new query --
+            Event::Final(text) => {
+                let Some((persona, utterance)) = self.barge_in_utterance(&text) else {
+                    return vec![];
+                };
+                self.listen(utterance, self.wake_timeout_chunks);
+                let mut actions = vec![
+                    log(format!("[Система]: Перебивание: «{}».", text)),
+                    Action::TruncateReply,
+                    Action::StopPlayback,
+                ];
+                if let Some(persona) = persona {
+                    actions.extend(self.select_persona(persona));
+                }
+                actions.extend([Action::Cue(Cue::Wake), Action::ResetRecognizer]);
+                actions
+            }
+
+            Event::Partial(_) | Event::Tick | Event::Alarm(_) => vec![],
+        }
+    }
+
+    /// Enter `ListeningQuery` with `text` already heard, going idle after
+    /// `idle_after` chunks of silence.
+    fn listen(&mut self, text: String, idle_after: u32) {
+        self.state = State::ListeningQuery;
+        self.accumulated_text = text;
+        self.partial_text.clear();
+        self.silence_counter = 0;
+        self.idle_after = idle_after;
+        self.query_chunks = 0;
+    }
+
+    /// Switch to `persona`, starting a fresh history if it is not the current one.
+    fn select_persona(&mut self, persona: usize) -> Vec<Action> {
+        if persona == self.persona {
+            return vec![];
+        }
+        self.persona = persona;
+        vec![Action::SelectPersona(persona), Action::ResetHistory]
+    }
+
+    /// Persona addressed and text following its wake word, if `text` contains one.
+    fn after_wake_word(&self, text: &str) -> Option<(usize, String)> {
+        self.wake.find(text).map(|m| (m.persona, m.remainder))
+    }
+
+    /// The query to continue with if `text` should interrupt playback, and
+    /// the persona it is addressed to when a wake word was used.
+    fn barge_in_utterance(&self, text: &str) -> Option<(Option<usize>, String)> {
+        match self.barge_in {
+            BargeIn::Off => None,
+            BargeIn::WakeWord => self
+                .after_wake_word(text)
+                .map(|(persona, rest)| (Some(persona), rest)),
+            BargeIn::Speech => {
+                if text.split_whitespace().count() < self.barge_in_min_words {
+                    return None;
+                }
+                Some(match self.after_wake_word(text) {
+                    Some((persona, rest)) => (Some(persona), rest),
+                    None => (None, text.to_string()),
+                })
+            }
+        }
+    }
+
+    fn has_stop_word(&self, text: &str) -> bool {
+        self.stop_words.iter().any(|w| text.contains(w.as_str()))
+    }
+}
+
+fn log(line: impl Into<String>) -> Action {
+    Action::Log(line.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn persona(name: &str) -> PersonaConfig {
+        PersonaConfig {
+            name: name.into(),
+            wake_word: vec![name.into()],
+            system_prompt: String::new(),
+            voice: String::new(),
+            model: String::new(),
+            tools: None,
+        }
+    }
+
+    /// 100 ms chunks: grace of 3 chunks, wake and follow-up timeouts of 5,
+    /// queries cut after 10.
+    fn conversation(barge_in: &str) -> Conversation {
+        let config: AssistantConfig =
+            toml::from_str(&format!("barge_in = \"{}\"", barge_in)).unwrap();
+        let timing = TimingConfig {
+            chunk_ms: 100,
+            grace_ms: 300,
+            wake_timeout_ms: 500,
+            follow_up_ms: 500,
+            max_query_ms: 1000,
+        };
+        Conversation::new(&config, &[persona("джарвис"), persona("алиса")], &timing)
+    }
+
+    /// Feed `events` and collect the actions, without log lines.
+    fn feed(c: &mut Conversation, events: impl IntoIterator<Item = Event>) -> Vec<Action> {
+        events
+            .into_iter()
+            .flat_map(|e| c.handle(e))
+            .filter(|a| !matches!(a, Action::Log(_)))
+            .collect()
+    }
+
+    fn fin(text: &str) -> Event {
+        Event::Final(text.into())
+    }
+
+    fn partial(text: &str) -> Event {
+        Event::Partial(text.into())
+    }
+
+    fn ticks(n: usize) -> impl Iterator<Item = Event> {
+        (0..n).map(|_| Event::Tick)
+    }
+
+    /// Wake up, ask `query` and get an answer that is being spoken.
+    fn speaking(c: &mut Conversation, query: &str) {
+        feed(c, [fin(&format!("джарвис {}", query)), fin("")]);
+        feed(c, [Event::Answer("ответ".into())]);
+        assert_eq!(c.state(), State::Speaking);
+    }
+
+    #[test]
+    fn idle_ignores_speech_without_wake_word() {
+        let mut c = conversation("off");
+        let actions = feed(
+            &mut c,
+            [partial("джарвис"), fin("включи свет"), Event::Tick],
+        );
+        assert!(actions.is_empty());
+        assert_eq!(c.state(), State::Idle);
+    }
+
+    #[test]
+    fn wake_word_starts_listening() {
+        let mut c = conversation("off");
+        assert_eq!(
+            feed(&mut c, [fin("привет джарвис")]),
+            [
+                Action::Cue(Cue::Wake),
+                Action::SelectPersona(0),
+                Action::ResetHistory,
+                Action::ResetRecognizer,
+            ]
+        );
+        assert_eq!(c.state(), State::ListeningQuery);
+    }
+
+    #[test]
+    fn wake_word_selects_its_persona() {
+        let mut c = conversation("off");
+        assert!(feed(&mut c, [fin("алиса")]).contains(&Action::SelectPersona(1)));
+    }
+
+    #[test]
+    fn query_after_wake_word_is_sent_at_end_of_utterance() {
+        let mut c = conversation("off");
+        feed(&mut c, [fin("джарвис включи свет")]);
+        assert_eq!(
+            feed(&mut c, [fin("")]),
+            [
+                Action::Cue(Cue::Query),
+                Action::Ask("включи свет".into()),
+                Action::ResetRecognizer,
+            ]
+        );
+        assert_eq!(c.state(), State::Speaking);
+    }
+
+    #[test]
+    fn grace_period_joins_phrases() {
+        let mut c = conversation("off");
+        feed(&mut c, [fin("джарвис"), fin("включи")]);
+        // A pause shorter than the grace period does not send the query.
+        assert!(feed(&mut c, ticks(2)).is_empty());
+        assert!(feed(&mut c, [partial("свет"), fin("свет")]).is_empty());
+        assert!(feed(&mut c, ticks(2)).is_empty());
+        assert_eq!(
+            feed(&mut c, ticks(1)),
+            [
+                Action::Cue(Cue::Query),
+                Action::Ask("включи свет".into()),
+                Action::ResetRecognizer,
+            ]
+        );
+    }
+
+    #[test]
+    fn silence_after_wake_word_goes_idle() {
+        let mut c = conversation("off");
+        feed(&mut c, [fin("джарвис")]);
+        assert!(feed(&mut c, ticks(4)).is_empty());
+        assert_eq!(
+            feed(&mut c, ticks(1)),
+            [
+                Action::Cue(Cue::Idle),
+                Action::ResetHistory,
+                Action::ResetRecognizer,
+            ]
+        );
+        assert_eq!(c.state(), State::Idle);
+    }
+
+    #[test]
+    fn long_query_is_cut() {
+        let mut c = conversation("off");
+        feed(&mut c, [fin("джарвис")]);
+        let words: Vec<Event> = (1..10).map(|n| partial(&"слово ".repeat(n))).collect();
+        assert!(feed(&mut c, words).is_empty());
+        let actions = feed(&mut c, [partial("слово ".repeat(10).trim())]);
+        assert!(actions.contains(&Action::Ask("слово ".repeat(10).trim().into())));
+    }
+
+    #[test]
+    fn stop_word_while_listening_goes_idle() {
+        let mut c = conversation("off");
+        feed(&mut c, [fin("джарвис включи")]);
+        assert_eq!(
+            feed(&mut c, [partial("стоп")]),
+            [
+                Action::Cue(Cue::Idle),
+                Action::ResetHistory,
+                Action::ResetRecognizer,
+            ]
+        );
+        assert_eq!(c.state(), State::Idle);
+    }
+
+    #[test]
+    fn stop_word_while_speaking_stops_playback() {
+        let mut c = conversation("off");
+        speaking(&mut c, "расскажи сказку");
+        assert_eq!(
+            feed(&mut c, [partial("хватит")]),
+            [
+                Action::StopPlayback,
+                Action::Cue(Cue::Idle),
+                Action::ResetHistory,
+                Action::ResetRecognizer,
+            ]
+        );
+        assert_eq!(c.state(), State::Idle);
+    }
+
+    #[test]
+    fn answer_is_spoken_then_follow_up_needs_no_wake_word() {
+        let mut c = conversation("off");
+        feed(&mut c, [fin("джарвис который час"), fin("")]);
+        assert_eq!(
+            feed(&mut c, [Event::Answer("десять".into())]),
+            [Action::Speak("десять".into())]
+        );
+        assert_eq!(
+            feed(&mut c, [Event::PlaybackFinished]),
+            [Action::Cue(Cue::FollowUp), Action::ResetRecognizer]
+        );
+        assert_eq!(c.state(), State::ListeningQuery);
+        let actions = feed(&mut c, [fin("а минут"), fin("")]);
+        assert!(actions.contains(&Action::Ask("а минут".into())));
+    }
+
+    #[test]
+    fn follow_up_times_out() {
+        let mut c = conversation("off");
+        speaking(&mut c, "который час");
+        feed(&mut c, [Event::PlaybackFinished]);
+        let actions = feed(&mut c, ticks(5));
+        assert!(actions.contains(&Action::Cue(Cue::Idle)));
+        assert_eq!(c.state(), State::Idle);
+    }
+
+    #[test]
+    fn failed_ask_listens_again() {
+        let mut c = conversation("off");
+        feed(&mut c, [fin("джарвис который час"), fin("")]);
+        assert_eq!(feed(&mut c, [Event::Failed]), [Action::ResetRecognizer]);
+        assert_eq!(c.state(), State::ListeningQuery);
+    }
+
+    #[test]
+    fn barge_in_off_ignores_speech() {
+        let mut c = conversation("off");
+        speaking(&mut c, "расскажи сказку");
+        assert!(feed(&mut c, [fin("джарвис который час")]).is_empty());
+        assert_eq!(c.state(), State::Speaking);
+    }
+
+    #[test]
+    fn barge_in_with_wake_word() {
+        let mut c = conversation("wake_word");
+        speaking(&mut c, "расскажи сказку");
+        assert!(feed(&mut c, [fin("который час")]).is_empty());
+        assert_eq!(
+            feed(&mut c, [fin("джарвис который час")]),
+            [
+                Action::TruncateReply,
+                Action::StopPlayback,
+                Action::Cue(Cue::Wake),
+                Action::ResetRecognizer,
+            ]
+        );
+        let actions = feed(&mut c, [fin("")]);
+        assert!(actions.contains(&Action::Ask("который час".into())));
+    }
+
+    #[test]
+    fn barge_in_with_other_wake_word_switches_persona() {
+        let mut c = conversation("wake_word");
+        speaking(&mut c, "расскажи сказку");
+        assert_eq!(
+            feed(&mut c, [fin("алиса который час")]),
+            [
+                Action::TruncateReply,
+                Action::StopPlayback,
+                Action::SelectPersona(1),
+                Action::ResetHistory,
+                Action::Cue(Cue::Wake),
+                Action::ResetRecognizer,
+            ]
+        );
+        let actions = feed(&mut c, [fin("")]);
+        assert!(actions.contains(&Action::Ask("который час".into())));
+    }
+
+    #[test]
+    fn follow_up_with_other_wake_word_switches_persona() {
+        let mut c = conversation("off");
+        speaking(&mut c, "который час");
+        feed(&mut c, [Event::PlaybackFinished]);
+        let actions = feed(&mut c, [fin("алиса а погода"), fin("")]);
+        assert_eq!(
+            actions,
+            [
+                Action::SelectPersona(1),
+                Action::ResetHistory,
+                Action::Cue(Cue::Query),
+                Action::Ask("а погода".into()),
+                Action::ResetRecognizer,
+            ]
+        );
+        // The same persona's wake word does not reset the conversation.
+        feed(&mut c, [Event::PlaybackFinished]);
+        let actions = feed(&mut c, [fin("алиса а завтра"), fin("")]);
+        assert!(!actions.contains(&Action::ResetHistory));
+    }
+
+    #[test]
+    fn barge_in_with_speech_needs_enough_words() {
+        let mut c = conversation("speech");
+        speaking(&mut c, "расскажи сказку");
+        assert!(feed(&mut c, [fin("ага")]).is_empty());
+        let actions = feed(&mut c, [fin("а теперь синий")]);
+        assert!(actions.contains(&Action::StopPlayback));
+        let actions = feed(&mut c, [fin("")]);
+        assert!(actions.contains(&Action::Ask("а теперь синий".into())));
+    }
+
+    #[test]
+    fn alarm_is_announced_when_idle() {
+        let mut c = conversation("off");
+        assert_eq!(
+            feed(&mut c, [Event::Alarm("Таймер сработал.".into())]),
+            [
+                Action::ResetHistory,
+                Action::Cue(Cue::Alarm),
+                Action::Announce("Таймер сработал.".into()),
+                Action::ResetRecognizer,
+            ]
+        );
+        assert_eq!(c.state(), State::Speaking);
+    }
+
+    #[test]
+    fn state_changes_are_logged() {
+        let mut c = conversation("off");
+        let actions = c.handle(fin("джарвис"));
+        assert!(matches!(&actions[0], Action::Log(line) if line.contains("джарвис")));
+    }
+}
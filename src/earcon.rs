@@ -0,0 +1,167 @@
+use std::f32::consts::TAU;
+use std::fs::File;
+use std::io::BufReader;
+
+use rodio::{Decoder, OutputStream, Sink, Source, buffer::SamplesBuffer};
+
+use crate::aec::EchoReference;
+use crate::config::EarconsConfig;
+use crate::conversation::Cue;
+
+const TONE_RATE: u32 = 22050;
+/// Fade applied to both ends of generated tones to avoid clicks.
+const FADE_MS: f32 = 5.0;
+/// Config value selecting the built-in tone for a cue.
+const BUILTIN: &str = "tone";
+
+// ---------------------------------------------------------------------------
+// Earcons — short sounds that confirm what the assistant is doing
+// ---------------------------------------------------------------------------
+
+struct Sound {
+    channels: u16,
+    rate: u32,
+    samples: Vec<f32>,
+}
+
+pub struct Earcons {
+    wake: Option<Sound>,
+    query: Option<Sound>,
+    idle: Option<Sound>,
+    follow_up: Option<Sound>,
+    error: Option<Sound>,
+    alarm: Option<Sound>,
+    echo: Option<EchoReference>,
+    /// The cue currently playing; the stream must outlive its playback.
+    playing: Option<(Sink, OutputStream)>,
+}
+
+impl Earcons {
+    /// Load or generate every configured cue.  A file that cannot be
+    /// decoded is reported and that cue stays silent.
+    pub fn new(config: &EarconsConfig, echo: Option<EchoReference>) -> Self {
+        let volume = config.volume.clamp(0.0, 1.0);
+        let load = |setting: &str, tones: &[(f32, f32)]| -> Option<Sound> {
+            let sound = match setting {
+                "" => return None,
+                BUILTIN => tone(tones),
+                path => match decode(path) {
+                    Ok(sound) => sound,
+                    Err(e) => {
+                        eprintln!("[Система]: Звуковой сигнал не загружен: {}", e);
+                        return None;
+                    }
+                },
+            };
+            Some(Sound {
+                samples: sound.samples.iter().map(|s| s * volume).collect(),
+                ..sound
+            })
+        };
+        Self {
+            wake: load(&config.wake, &[(660.0, 70.0), (880.0, 90.0)]),
+            query: load(&config.query, &[(880.0, 60.0)]),
+            idle: load(&config.idle, &[(880.0, 70.0), (660.0, 90.0)]),
+            follow_up: load(&config.follow_up, &[(990.0, 50.0)]),
+            error: load(
+                &config.error,
+                &[(330.0, 120.0), (0.0, 60.0), (330.0, 120.0)],
+            ),
+            alarm: load(
+                &config.alarm,
+                &[
+                    (880.0, 150.0),
+                    (0.0, 100.0),
+                    (880.0, 150.0),
+                    (0.0, 100.0),
+                    (880.0, 150.0),
+                    (0.0, 300.0),
+                    (880.0, 150.0),
+                    (0.0, 100.0),
+                    (880.0, 150.0),
+                    (0.0, 100.0),
+                    (880.0, 150.0),
+                ],
+            ),
+            echo,
+            playing: None,
+        }
+    }
+
+    /// Start playing the sound for `cue`, cutting off the previous one.
+    /// Does not wait for it to finish.
+    pub fn play(&mut self, cue: Cue) {
+        let sound = match cue {
+            Cue::Wake => &self.wake,
+            Cue::Query => &self.query,
+            Cue::Idle => &self.idle,
+            Cue::FollowUp => &self.follow_up,
+            Cue::Error => &self.error,
+            Cue::Alarm => &self.alarm,
+        };
+        let Some(sound) = sound else {
+            return;
+        };
+
+        self.playing = None;
+        let (stream, handle) = match OutputStream::try_default() {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("[Система]: Звуковой сигнал не воспроизведён: {}", e);
+                return;
+            }
+        };
+        let sink = match Sink::try_new(&handle) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("[Система]: Звуковой сигнал не воспроизведён: {}", e);
+                return;
+            }
+        };
+
+        let source = SamplesBuffer::new(sound.channels, sound.rate, sound.samples.clone());
+        match &self.echo {
+            Some(echo) => sink.append(echo.tap(source)),
+            None => sink.append(source),
+        }
+        self.playing = Some((sink, stream));
+    }
+
+    /// Block until the cue being played has finished.
+    pub fn wait(&self) {
+        if let Some((sink, _)) = &self.playing {
+            sink.sleep_until_end();
+        }
+    }
+}
+
+/// Sine beeps of `(frequency Hz, duration ms)`; a zero frequency is a pause.
+fn tone(parts: &[(f32, f32)]) -> Sound {
+    let fade = (FADE_MS * TONE_RATE as f32 / 1000.0) as usize;
+    let mut samples = Vec::new();
+    for &(freq, ms) in parts {
+        let n = (ms * TONE_RATE as f32 / 1000.0) as usize;
+        samples.extend((0..n).map(|i| {
+            if freq == 0.0 {
+                return 0.0;
+            }
+            let envelope = (i.min(n - 1 - i) as f32 / fade as f32).min(1.0);
+            envelope * (TAU * freq * i as f32 / TONE_RATE as f32).sin()
+        }));
+    }
+    Sound {
+        channels: 1,
+        rate: TONE_RATE,
+        samples,
+    }
+}
+
+fn decode(path: &str) -> Result<Sound, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let decoder = Decoder::new(BufReader::new(file)).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(Sound {
+        channels: decoder.channels(),
+        rate: decoder.sample_rate(),
+        samples: decoder.convert_samples().collect(),
+    })
+}
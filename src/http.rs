@@ -0,0 +1,235 @@
+use std::time::Duration;
+
+use reqwest::Method;
+use serde_json::Value;
+
+// ---------------------------------------------------------------------------
+// In-process HTTP requests for `kind = "http"` tools
+// ---------------------------------------------------------------------------
+
+pub struct Request<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    pub timeout: Duration,
+    pub max_body_bytes: usize,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+    /// The body was longer than `max_body_bytes` and has been cut.
+    pub truncated: bool,
+}
+
+/// Perform the request and return the response whatever its status code;
+/// only transport failures (connection refused, DNS, timeout…) are errors.
+pub fn send(req: &Request) -> Result<Response, String> {
+    // Tool calls are made from inside the LLM client's runtime, where
+    // blocking on another one is not allowed, so the request gets a thread.
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| e.to_string())?;
+            rt.block_on(send_async(req))
+        })
+        .join()
+        .unwrap_or_else(|_| Err("запрос аварийно завершился".into()))
+    })
+}
+
+async fn send_async(req: &Request<'_>) -> Result<Response, String> {
+    let client = reqwest::Client::builder()
+        .timeout(req.timeout)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let method = Method::from_bytes(req.method.to_uppercase().as_bytes())
+        .map_err(|_| format!("неверный метод {}", req.method))?;
+    let mut request = client.request(method, req.url);
+    for (name, value) in &req.headers {
+        request = request.header(name, value);
+    }
+    if let Some(body) = &req.body {
+        if !req
+            .headers
+            .iter()
+            .any(|(n, _)| n.eq_ignore_ascii_case("content-type"))
+        {
+            request = request.header("Content-Type", "application/json");
+        }
+        request = request.body(body.clone());
+    }
+
+    let mut response = request.send().await.map_err(describe)?;
+    let status = response.status().as_u16();
+    let mut bytes = Vec::new();
+    while bytes.len() <= req.max_body_bytes
+        && let Some(chunk) = response.chunk().await.map_err(describe)?
+    {
+        bytes.extend_from_slice(&chunk);
+    }
+    let truncated = bytes.len() > req.max_body_bytes;
+    bytes.truncate(req.max_body_bytes);
+
+    Ok(Response {
+        status,
+        body: String::from_utf8_lossy(&bytes).trim().to_string(),
+        truncated,
+    })
+}
+
+/// The error with its causes, e.g. «error sending request: connection refused».
+fn describe(e: reqwest::Error) -> String {
+    let mut text = e.to_string();
+    let mut source = std::error::Error::source(&e);
+    while let Some(cause) = source {
+        text.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    text
+}
+
+/// Look up a value by a JSONPath-style expression: `$.state.is_enabled`,
+/// `devices[0].name`, `$['on']`.  Only member and index steps are supported.
+pub fn extract<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = value;
+    let mut rest = path.trim().strip_prefix('$').unwrap_or(path.trim());
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            current = current.get(&r[..end])?;
+            rest = &r[end..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']')?;
+            let key = r[..end].trim();
+            current = match key.parse::<usize>() {
+                Ok(idx) => current.get(idx)?,
+                Err(_) => current.get(key.trim_matches(|c| c == '\'' || c == '"'))?,
+            };
+            rest = &r[end + 1..];
+        } else {
+            // Leading bare member name without `$.`
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            current = current.get(&rest[..end])?;
+            rest = &rest[end..];
+        }
+    }
+    Some(current)
+}
+
+/// Percent-encode a value for use inside a URL path segment or query.
+pub fn url_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Answer one request with `status` and `body`; returns the URL and the
+    /// request as received.
+    fn serve_once(status: &str, body: &str) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/path", listener.local_addr().unwrap());
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            // Headers, then as much body as they announce.
+            while !is_complete(&request) {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8_lossy(&request).into_owned()
+        });
+        (url, handle)
+    }
+
+    fn is_complete(request: &[u8]) -> bool {
+        let text = String::from_utf8_lossy(request).to_lowercase();
+        let Some(end) = text.find("\r\n\r\n") else {
+            return false;
+        };
+        let length = text
+            .lines()
+            .find_map(|l| l.strip_prefix("content-length:"))
+            .and_then(|l| l.trim().parse().ok())
+            .unwrap_or(0);
+        request.len() >= end + 4 + length
+    }
+
+    fn request<'a>(method: &'a str, url: &'a str, body: Option<&str>) -> Request<'a> {
+        Request {
+            method,
+            url,
+            headers: vec![("X-Token".into(), "abc".into())],
+            body: body.map(String::from),
+            timeout: Duration::from_secs(5),
+            max_body_bytes: 8,
+        }
+    }
+
+    #[test]
+    fn error_statuses_are_responses() {
+        let (url, server) = serve_once("404 Not Found", "нет");
+        let response = send(&request("get", &url, None)).unwrap();
+        assert_eq!((response.status, response.body.as_str()), (404, "нет"));
+        assert!(server.join().unwrap().starts_with("GET /path"));
+    }
+
+    #[test]
+    fn body_is_sent_as_json_and_reply_is_cut() {
+        let (url, server) = serve_once("200 OK", "0123456789");
+        let response = send(&request("PUT", &url, Some(r#"{"on":true}"#))).unwrap();
+        assert_eq!(response.body, "01234567");
+        assert!(response.truncated);
+        let received = server.join().unwrap().to_lowercase();
+        assert!(received.contains("content-type: application/json"));
+        assert!(received.contains("x-token: abc"));
+        assert!(received.ends_with(r#"{"on":true}"#));
+    }
+
+    #[test]
+    fn unreachable_host_is_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        assert!(send(&request("GET", &url, None)).is_err());
+    }
+
+    #[test]
+    fn extract_follows_members_and_indexes() {
+        let value = json!({ "state": { "on": true }, "devices": [{ "name": "лампа" }] });
+        assert_eq!(extract(&value, "$.state.on"), Some(&json!(true)));
+        assert_eq!(extract(&value, "devices[0].name"), Some(&json!("лампа")));
+        assert_eq!(extract(&value, "$['state']['on']"), Some(&json!(true)));
+        assert_eq!(extract(&value, "$.missing"), None);
+    }
+
+    #[test]
+    fn url_encode_escapes_reserved_bytes() {
+        assert_eq!(url_encode("a b/ж"), "a%20b%2F%D0%B6");
+    }
+}
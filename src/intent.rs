@@ -0,0 +1,505 @@
+use std::collections::HashMap;
+
+use serde_json::{Value, json};
+
+use crate::config::{IntentConfig, SlotConfig, SlotKind};
+
+/// Longest number accepted in words, e.g. «девятьсот девяносто девять».
+const MAX_NUMBER_WORDS: usize = 3;
+
+/// Colors by the start of their Russian name, so that every ending matches.
+const COLORS: &[(&str, [u8; 3])] = &[
+    ("красн", [255, 0, 0]),
+    ("оранжев", [255, 165, 0]),
+    ("желт", [255, 255, 0]),
+    ("зелен", [0, 255, 0]),
+    ("голуб", [0, 191, 255]),
+    ("бирюзов", [64, 224, 208]),
+    ("син", [0, 0, 255]),
+    ("фиолетов", [128, 0, 255]),
+    ("сиренев", [200, 160, 255]),
+    ("розов", [255, 105, 180]),
+    ("бел", [255, 255, 255]),
+];
+
+// ---------------------------------------------------------------------------
+// IntentEngine — fixed sentences answered without the LLM
+// ---------------------------------------------------------------------------
+
+enum Token {
+    Word(String),
+    Slot(String),
+    /// Words that may be left out, written `[пожалуйста]`.
+    Optional(Vec<Token>),
+}
+
+#[derive(Clone)]
+enum SlotValue {
+    Text(String),
+    Number(i64),
+    Color([u8; 3]),
+}
+
+/// A transcript that matched one of the `[[intent]]` sentences.
+pub struct IntentMatch<'a> {
+    config: &'a IntentConfig,
+    /// Slot name → (words as heard, value).
+    slots: HashMap<String, (String, SlotValue)>,
+}
+
+pub struct IntentEngine<'a> {
+    /// Each intent with its parsed sentences.
+    intents: Vec<(&'a IntentConfig, Vec<Vec<Token>>)>,
+}
+
+impl<'a> IntentEngine<'a> {
+    pub fn new(configs: &'a [IntentConfig]) -> Self {
+        let intents: Vec<_> = configs
+            .iter()
+            .map(|c| (c, c.sentences.iter().map(|s| parse(s)).collect()))
+            .collect();
+        if !intents.is_empty() {
+            eprintln!("[Интенты]: загружено {}", intents.len());
+        }
+        Self { intents }
+    }
+
+    /// The first intent one of whose sentences matches the whole of `text`.
+    pub fn find(&self, text: &str) -> Option<IntentMatch<'a>> {
+        let words = words(text);
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        for (config, sentences) in &self.intents {
+            for tokens in sentences {
+                let refs: Vec<&Token> = tokens.iter().collect();
+                let mut slots = Vec::new();
+                if match_tokens(&refs, &words, &config.slots, &mut slots) {
+                    let slots = slots
+                        .into_iter()
+                        .map(|(name, heard, value)| (name, (heard, value)))
+                        .collect();
+                    let m = IntentMatch { config, slots };
+                    eprintln!("[Интент «{}»]: {}", m.name(), text);
+                    return Some(m);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl IntentMatch<'_> {
+    pub fn name(&self) -> &str {
+        match self.config.name.as_str() {
+            "" => &self.config.sentences[0],
+            name => name,
+        }
+    }
+
+    /// The tool to call, if any.
+    pub fn tool(&self) -> Option<&str> {
+        self.config.tool.as_deref()
+    }
+
+    /// Tool arguments: the `args` templates filled with slot values, or
+    /// every slot by its name when none are given.
+    pub fn args(&self) -> Value {
+        if self.config.args.is_empty() {
+            return self
+                .slots
+                .iter()
+                .map(|(name, (_, value))| (name.clone(), slot_json(value)))
+                .collect::<serde_json::Map<_, _>>()
+                .into();
+        }
+        self.config
+            .args
+            .iter()
+            .map(|(key, template)| (key.clone(), render(template, |k| self.arg_value(k))))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
+    /// The spoken reply, with slots as heard and `{result}` as the tool's output.
+    pub fn reply(&self, result: &str) -> String {
+        let value = render(&self.config.reply, |key| match key {
+            "result" => Some(json!(result)),
+            _ => self.slots.get(key).map(|(heard, _)| json!(heard)),
+        });
+        match value {
+            Value::String(s) => s,
+            other => other.to_string(),
+        }
+    }
+
+    /// `{slot}` or, for colors, `{slot.red}`, `{slot.green}`, `{slot.blue}`.
+    fn arg_value(&self, key: &str) -> Option<Value> {
+        let (name, part) = key.split_once('.').unwrap_or((key, ""));
+        let (_, value) = self.slots.get(name)?;
+        match (value, part) {
+            (_, "") => Some(slot_json(value)),
+            (SlotValue::Color([r, _, _]), "red") => Some(json!(r)),
+            (SlotValue::Color([_, g, _]), "green") => Some(json!(g)),
+            (SlotValue::Color([_, _, b]), "blue") => Some(json!(b)),
+            _ => None,
+        }
+    }
+}
+
+/// Lowercased words of `text`, with «ё» read as «е».
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .replace('ё', "е")
+        .split(|c: char| !c.is_alphanumeric() && c != '-')
+        .filter(|w| !w.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Split a sentence template into words, `{slot}`s and `[optional words]`.
+fn parse(sentence: &str) -> Vec<Token> {
+    let mut stack: Vec<Vec<Token>> = vec![Vec::new()];
+    let mut rest = sentence;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '[' => {
+                stack.push(Vec::new());
+                rest = &rest[1..];
+            }
+            ']' if stack.len() > 1 => {
+                let group = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(Token::Optional(group));
+                rest = &rest[1..];
+            }
+            '{' => {
+                let end = rest.find('}').unwrap_or(rest.len());
+                let name = rest[1..end].trim().to_string();
+                stack.last_mut().unwrap().push(Token::Slot(name));
+                rest = &rest[(end + 1).min(rest.len())..];
+            }
+            _ => {
+                let end = rest.find(['[', ']', '{']).unwrap_or(rest.len());
+                let tokens = stack.last_mut().unwrap();
+                tokens.extend(words(&rest[..end]).into_iter().map(Token::Word));
+                rest = &rest[end.max(c.len_utf8())..];
+            }
+        }
+    }
+    // Unclosed brackets are taken as closed at the end.
+    while stack.len() > 1 {
+        let group = stack.pop().unwrap();
+        stack.last_mut().unwrap().push(Token::Optional(group));
+    }
+    stack.pop().unwrap()
+}
+
+/// Match `tokens` against all of `words`, trying every way a slot can be
+/// filled; matched slots are collected into `slots`.
+fn match_tokens(
+    tokens: &[&Token],
+    words: &[&str],
+    configs: &HashMap<String, SlotConfig>,
+    slots: &mut Vec<(String, String, SlotValue)>,
+) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return words.is_empty();
+    };
+    match token {
+        Token::Word(w) => {
+            words.first() == Some(&w.as_str()) && match_tokens(rest, &words[1..], configs, slots)
+        }
+        Token::Optional(inner) => {
+            let with: Vec<&Token> = inner.iter().chain(rest.iter().copied()).collect();
+            match_tokens(&with, words, configs, slots) || match_tokens(rest, words, configs, slots)
+        }
+        Token::Slot(name) => {
+            for (value, len) in candidates(configs.get(name), words) {
+                slots.push((name.clone(), words[..len].join(" "), value));
+                if match_tokens(rest, &words[len..], configs, slots) {
+                    return true;
+                }
+                slots.pop();
+            }
+            false
+        }
+    }
+}
+
+/// Ways the slot can take words from the start of `words`: its value and
+/// the number of words it takes.  A slot without a `[intent.slots.*]`
+/// entry takes any text.
+fn candidates(config: Option<&SlotConfig>, words: &[&str]) -> Vec<(SlotValue, usize)> {
+    let kind = config.map_or(SlotKind::Text, |c| c.kind);
+    match kind {
+        SlotKind::List => {
+            let mut found: Vec<(SlotValue, usize)> = config
+                .into_iter()
+                .flat_map(|c| &c.values)
+                .filter_map(|(spoken, value)| {
+                    let spoken = self::words(spoken);
+                    let len = spoken.len();
+                    (len > 0 && words.len() >= len && words[..len] == spoken[..])
+                        .then(|| (SlotValue::Text(value.clone()), len))
+                })
+                .collect();
+            // Longer names first: «свет в коридоре» before «свет».
+            found.sort_by_key(|(_, len)| std::cmp::Reverse(*len));
+            found
+        }
+        SlotKind::Number => (1..=words.len().min(MAX_NUMBER_WORDS))
+            .rev()
+            .filter_map(|len| parse_number(&words[..len]).map(|n| (SlotValue::Number(n), len)))
+            .collect(),
+        SlotKind::Color => words
+            .first()
+            .and_then(|w| COLORS.iter().find(|(stem, _)| w.starts_with(stem)))
+            .map(|&(_, rgb)| (SlotValue::Color(rgb), 1))
+            .into_iter()
+            .collect(),
+        SlotKind::Text => (1..=words.len())
+            .map(|len| (SlotValue::Text(words[..len].join(" ")), len))
+            .collect(),
+    }
+}
+
+fn slot_json(value: &SlotValue) -> Value {
+    match value {
+        SlotValue::Text(s) => json!(s),
+        SlotValue::Number(n) => json!(n),
+        SlotValue::Color([r, g, b]) => json!(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+    }
+}
+
+/// Replace every `{key}` in `template`.  A template that is a single
+/// placeholder keeps the value's JSON type, so numbers stay numbers.
+fn render(template: &str, lookup: impl Fn(&str) -> Option<Value>) -> Value {
+    let trimmed = template.trim();
+    if let Some(key) = trimmed.strip_prefix('{').and_then(|t| t.strip_suffix('}'))
+        && !key.contains(['{', '}'])
+        && let Some(value) = lookup(key.trim())
+    {
+        return value;
+    }
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let key = rest[start + 1..start + len].trim();
+        match lookup(key) {
+            Some(Value::String(s)) => out.push_str(&s),
+            Some(value) => out.push_str(&value.to_string()),
+            None => out.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    json!(out)
+}
+
+/// A number from 0 to 999 in digits or Russian words.
+fn parse_number(words: &[&str]) -> Option<i64> {
+    if let [word] = words
+        && word.bytes().all(|b| b.is_ascii_digit())
+    {
+        return word.parse().ok().filter(|n| *n <= 999);
+    }
+    let mut total = 0;
+    // Place of the previous word; each word must be of a lower one.
+    let mut last = 1000;
+    for word in words {
+        let value = number_word(word)?;
+        let place = match value {
+            100.. => 100,
+            10.. => 10,
+            _ => 1,
+        };
+        if place >= last {
+            return None;
+        }
+        total += value;
+        // Nothing follows «пятнадцать».
+        last = if (10..20).contains(&value) { 1 } else { place };
+    }
+    Some(total)
+}
+
+fn number_word(word: &str) -> Option<i64> {
+    Some(match word {
+        "ноль" | "нуль" => 0,
+        "один" | "одна" | "одну" | "одно" => 1,
+        "два" | "две" => 2,
+        "три" => 3,
+        "четыре" => 4,
+        "пять" => 5,
+        "шесть" => 6,
+        "семь" => 7,
+        "восемь" => 8,
+        "девять" => 9,
+        "десять" => 10,
+        "одиннадцать" => 11,
+        "двенадцать" => 12,
+        "тринадцать" => 13,
+        "четырнадцать" => 14,
+        "пятнадцать" => 15,
+        "шестнадцать" => 16,
+        "семнадцать" => 17,
+        "восемнадцать" => 18,
+        "девятнадцать" => 19,
+        "двадцать" => 20,
+        "тридцать" => 30,
+        "сорок" => 40,
+        "пятьдесят" => 50,
+        "шестьдесят" => 60,
+        "семьдесят" => 70,
+        "восемьдесят" => 80,
+        "девяносто" => 90,
+        "сто" => 100,
+        "двести" => 200,
+        "триста" => 300,
+        "четыреста" => 400,
+        "пятьсот" => 500,
+        "шестьсот" => 600,
+        "семьсот" => 700,
+        "восемьсот" => 800,
+        "девятьсот" => 900,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intent(toml: &str) -> IntentConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    /// The slots of `config` matched against `text`, as heard.
+    fn matched(config: &IntentConfig, text: &str) -> Option<HashMap<String, String>> {
+        let m = IntentEngine::new(std::slice::from_ref(config)).find(text)?;
+        Some(
+            m.slots
+                .into_iter()
+                .map(|(k, (heard, _))| (k, heard))
+                .collect(),
+        )
+    }
+
+    fn number(text: &str) -> Option<i64> {
+        let words = words(text);
+        parse_number(&words.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn templates_parse_into_words_slots_and_groups() {
+        let tokens = parse("Включи [свет [в]] {room}");
+        assert!(matches!(&tokens[0], Token::Word(w) if w == "включи"));
+        let Token::Optional(group) = &tokens[1] else {
+            panic!("no optional group");
+        };
+        assert!(matches!(&group[0], Token::Word(w) if w == "свет"));
+        assert!(matches!(&group[1], Token::Optional(inner) if inner.len() == 1));
+        assert!(matches!(&tokens[2], Token::Slot(s) if s == "room"));
+        // An unclosed group runs to the end.
+        assert!(matches!(&parse("скажи [пожалуйста")[1], Token::Optional(g) if g.len() == 1));
+    }
+
+    #[test]
+    fn optional_words_may_be_left_out() {
+        let config = intent(r#"sentences = "[скажи] который [сейчас] час""#);
+        for text in [
+            "который час",
+            "скажи который час",
+            "Скажи, который сейчас час?",
+        ] {
+            assert!(matched(&config, text).is_some(), "{}", text);
+        }
+        assert!(matched(&config, "который час скажи").is_none());
+    }
+
+    #[test]
+    fn text_slots_backtrack_to_fit_the_rest() {
+        let config = intent(r#"sentences = "напомни {what} в {time}""#);
+        let slots = matched(&config, "напомни выключить свет в кухне в семь").unwrap();
+        assert_eq!(slots["what"], "выключить свет");
+        assert_eq!(slots["time"], "кухне в семь");
+    }
+
+    #[test]
+    fn list_slots_prefer_longer_names() {
+        let config = intent(
+            r#"
+            sentences = "включи {device} [пожалуйста]"
+            [slots.device]
+            values = { "свет" = "light.all", "свет в коридоре" = "light.hall" }
+            "#,
+        );
+        let slot = config.slots.get("device");
+        let words = ["свет", "в", "коридоре"];
+        let found = candidates(slot, &words);
+        assert_eq!(found.len(), 2);
+        assert!(matches!(&found[0], (SlotValue::Text(v), 3) if v == "light.hall"));
+        assert!(matches!(&found[1], (SlotValue::Text(v), 1) if v == "light.all"));
+
+        let m = IntentEngine::new(std::slice::from_ref(&config))
+            .find("включи свет пожалуйста")
+            .unwrap();
+        assert_eq!(m.args(), json!({ "device": "light.all" }));
+    }
+
+    #[test]
+    fn colors_match_by_stem() {
+        let config = intent(
+            r#"
+            sentences = "сделай подсветку {color}"
+            args = { red = "{color.red}", blue = "{color.blue}", hex = "{color}" }
+            [slots.color]
+            kind = "color"
+            "#,
+        );
+        for text in ["сделай подсветку синей", "сделай подсветку синюю"]
+        {
+            let m = IntentEngine::new(std::slice::from_ref(&config))
+                .find(text)
+                .unwrap();
+            assert_eq!(m.args(), json!({ "red": 0, "blue": 255, "hex": "#0000ff" }));
+        }
+        assert!(candidates(config.slots.get("color"), &["серобуромалиновый"]).is_empty());
+    }
+
+    #[test]
+    fn numbers_in_words_and_digits() {
+        assert_eq!(number("ноль"), Some(0));
+        assert_eq!(number("двадцать пять"), Some(25));
+        assert_eq!(number("пятнадцать"), Some(15));
+        assert_eq!(number("сто одна"), Some(101));
+        assert_eq!(number("девятьсот девяносто девять"), Some(999));
+        assert_eq!(number("42"), Some(42));
+        assert_eq!(number("999"), Some(999));
+        // Out of range or not a number.
+        assert_eq!(number("1000"), None);
+        assert_eq!(number("-5"), None);
+        assert_eq!(number("пять двадцать"), None);
+        assert_eq!(number("пятнадцать три"), None);
+        assert_eq!(number("двадцать двадцать"), None);
+    }
+
+    #[test]
+    fn number_slots_take_the_longest_number() {
+        let config = intent(
+            r#"
+            sentences = "таймер на {n} минут"
+            [slots.n]
+            kind = "number"
+            "#,
+        );
+        let m = IntentEngine::new(std::slice::from_ref(&config))
+            .find("таймер на двадцать пять минут")
+            .unwrap();
+        assert_eq!(m.args(), json!({ "n": 25 }));
+        assert!(matched(&config, "таймер на 1000 минут").is_none());
+    }
+}
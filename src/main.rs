@@ -1,32 +1,46 @@
 #![allow(dead_code)]
 
+mod aec;
+mod audio;
+mod backoff;
 mod config;
+mod context;
+mod conversation;
+mod earcon;
+mod http;
+mod intent;
+mod mcp;
+mod memory;
 mod openai;
+mod persist;
+mod process;
+mod scheduler;
 mod tools;
 mod tts;
+mod vad;
+mod wake;
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
 
 use chrono::*;
 use voskrust::api::*;
-use voskrust::sound::*;
 
-use config::Config;
-use openai::{Message, OpenAi};
+use aec::{EchoCanceller, EchoReference};
+use audio::{AudioError, AudioSource};
+use backoff::Backoff;
+use config::{Config, PersonaConfig};
+use context::Context;
+use conversation::{Action, Conversation, Cue, Event, State};
+use earcon::Earcons;
+use intent::IntentEngine;
+use memory::Memory;
+use openai::OpenAi;
+use scheduler::Scheduler;
 use tools::ToolManager;
 use tts::{SpeakHandle, Tts};
-
-const CONTINUATION_CHUNKS: u32 = 3; // ~300 ms grace period after final result for multi-sentence
-const SILENCE_TO_IDLE_CHUNKS: u32 = 20; // ~1.0 s of silence after response → idle
-
-// ---------------------------------------------------------------------------
-// State machine
-// ---------------------------------------------------------------------------
-
-#[derive(PartialEq)]
-enum AppState {
-    Idle,
-    ListeningQuery,
-    Speaking,
-}
+use vad::Vad;
+use wake::WakeSpotter;
 
 // ---------------------------------------------------------------------------
 // Entry point
@@ -45,191 +59,404 @@ fn main() {
     });
 
     // ---- vosk model ----
-    let model = Model::new(&config.vosk.model_path).unwrap();
+    let Some(model) = Model::new(&config.vosk.model_path) else {
+        eprintln!("Ошибка загрузки модели Vosk: {}", config.vosk.model_path);
+        std::process::exit(1);
+    };
 
     // ---- OpenAI client ----
-    let ai = OpenAi::new(&config.openai.model);
+    let mut ai = OpenAi::new(&config.openai).unwrap_or_else(|e| {
+        eprintln!("Ошибка настройки OpenAI: {}", e);
+        std::process::exit(1);
+    });
 
     // ---- tools ----
-    let tool_mgr = ToolManager::new(config.tool);
+    let memory = config.memory.enabled.then(|| {
+        Memory::load(&config.memory).unwrap_or_else(|e| {
+            eprintln!("Ошибка загрузки памяти: {}", e);
+            std::process::exit(1);
+        })
+    });
+    let scheduler = config.timers.enabled.then(|| {
+        Scheduler::load(&config.timers).unwrap_or_else(|e| {
+            eprintln!("Ошибка загрузки таймеров: {}", e);
+            std::process::exit(1);
+        })
+    });
+    let tool_mgr = ToolManager::new(
+        config.tool,
+        config.tool_defaults,
+        &config.mcp_server,
+        memory,
+        scheduler,
+    );
+    // The persona's prompt with what the assistant remembers, for personas
+    // that may read the memory at all.
+    let system_prompt = |persona: &PersonaConfig| match tool_mgr.memory() {
+        Some(memory) if persona.allows_tool("recall_facts") => {
+            memory.extend_prompt(&persona.system_prompt)
+        }
+        _ => persona.system_prompt.clone(),
+    };
+
+    // ---- offline intents ----
+    let intents = IntentEngine::new(&config.intent);
+
+    // ---- echo cancellation ----
+    let echo_ref = config.aec.enabled.then(EchoReference::default);
+    let mut aec = echo_ref.clone().map(|r| EchoCanceller::new(&config.aec, r));
+
+    // ---- voice activity detection ----
+    let mut vad = config
+        .vad
+        .enabled
+        .then(|| Vad::new(&config.vad, &config.timing));
 
     // ---- TTS ----
-    let tts = Tts::new(&config.tts.model_path);
+    let mut earcons = Earcons::new(&config.earcons, echo_ref.clone());
+    let mut voices: HashMap<&str, Tts> = HashMap::new();
+    for persona in &config.persona {
+        if voices.contains_key(persona.voice.as_str()) {
+            continue;
+        }
+        let tts = Tts::new(&persona.voice, echo_ref.clone()).unwrap_or_else(|e| {
+            eprintln!("Ошибка TTS: {}", e);
+            std::process::exit(1);
+        });
+        voices.insert(&persona.voice, tts);
+    }
 
     // ---- main-loop state ----
     let mut recognizer: Option<Recognizer> = None;
-    let mut audioreader: Option<ParecStream> = None;
+    let mut spotter: Option<WakeSpotter> = None;
+    let mut audioreader: Option<Box<dyn AudioSource>> = None;
+    let mut input_backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+    // Timers and reminders being announced, removed once speech starts.
+    let mut announcing: Vec<u64> = Vec::new();
 
-    let mut state = AppState::Idle;
-    let mut accumulated_text = String::new();
-    let mut silence_counter: u32 = 0;
-    let mut history: Vec<Message> = openai::initial_history(&config.assistant.system_prompt);
+    let mut conversation = Conversation::new(&config.assistant, &config.persona, &config.timing);
+    let mut persona = &config.persona[0];
+    let mut context = Context::load(&config.context, persona, &system_prompt(persona));
     let mut speak_handle: Option<SpeakHandle> = None;
+    // Audio has been fed to the recognizer since its last final result.
+    let mut utterance_open = false;
 
     eprintln!("[Система]: Голосовой ассистент запущен.");
-    eprintln!(
-        "[Система]: Скажите «{}» для активации.",
-        config.assistant.wake_word
-    );
+    for p in &config.persona {
+        eprintln!(
+            "[Система]: Скажите «{}» для активации «{}».",
+            p.wake_word.join("», «"),
+            p.name
+        );
+    }
 
     loop {
         // ---- time-range gate ----
         let hour = Local::now().hour();
         if hour < config.time_range.start_hour || hour >= config.time_range.end_hour {
             recognizer = None;
+            spotter = None;
             audioreader = None;
+            utterance_open = false;
             if let Some(h) = speak_handle.take() {
                 h.stop();
             }
-            state = AppState::Idle;
-            accumulated_text.clear();
-            silence_counter = 0;
-            history = openai::initial_history(&config.assistant.system_prompt);
-            std::thread::sleep(std::time::Duration::from_secs(60));
+            conversation.reset();
+            context.start(persona, &system_prompt(persona));
+            std::thread::sleep(Duration::from_secs(60));
             continue;
         }
 
         // ---- ensure recognizer & audio stream ----
-        if recognizer.is_none() {
-            recognizer = Some(Recognizer::new(&model, 16000f32));
+        let spotting = config.vosk.idle_grammar && conversation.state() == State::Idle;
+        if spotting {
+            if spotter.is_none() {
+                spotter = Some(WakeSpotter::new(&model, &config.persona));
+            }
+        } else if recognizer.is_none() {
+            recognizer = Some(Recognizer::new(&model, audio::SAMPLE_RATE as f32));
+            utterance_open = false;
         }
-        if audioreader.is_none() {
-            audioreader = Some(ParecStream::init().unwrap());
+        // ---- due timers and reminders interrupt the idle state ----
+        // Checked before the audio input, so they go off even while it is down.
+        let mut actions: VecDeque<Action> = VecDeque::new();
+        if conversation.state() == State::Idle
+            && let Some((ids, text)) = tool_mgr.scheduler().and_then(Scheduler::due)
+        {
+            announcing = ids;
+            actions.extend(conversation.handle(Event::Alarm(text)));
         }
 
-        // ---- read 100 ms of audio ----
-        let buf = {
-            let ar = audioreader.as_mut().unwrap();
-            ar.read_n_milliseconds(100.0).unwrap()
-        };
-
-        // ---- speech recognition ----
-        let (text, is_final) = {
-            let rec = recognizer.as_mut().unwrap();
-            if rec.accept_waveform(&buf[..]) {
-                (rec.final_result(), true)
-            } else {
-                (rec.partial_result(), false)
+        // ---- read one chunk of audio ----
+        // `None` while the input is down: the state machine still gets a tick,
+        // and the wait before reconnecting comes after the actions have run.
+        let mut input = None;
+        let mut reconnect_in = None;
+        if audioreader.is_none() {
+            match audio::open(&config.audio) {
+                Ok(source) => audioreader = Some(source),
+                // The sound server may come back: retry with growing delays
+                Err(AudioError::Device(e)) => {
+                    let delay = input_backoff.next_delay();
+                    eprintln!(
+                        "[Система]: Аудиовход недоступен ({}), повтор через {} с.",
+                        e,
+                        delay.as_secs()
+                    );
+                    reconnect_in = Some(delay);
+                }
+                Err(e) => {
+                    eprintln!("Ошибка аудиовхода: {}", e);
+                    std::process::exit(1);
+                }
             }
-        };
-
-        // ---- state machine ----
-        match state {
-            // ====================== IDLE ======================
-            AppState::Idle => {
-                if is_final {
-                    if let Some(pos) = text.find(&*config.assistant.wake_word) {
-                        let remainder = text[pos + config.assistant.wake_word.len()..]
-                            .trim()
-                            .to_string();
-
-                        state = AppState::ListeningQuery;
-                        accumulated_text = remainder;
-                        silence_counter = 0;
-                        history = openai::initial_history(&config.assistant.system_prompt);
-                        recognizer = None;
-                    }
+        }
+        if let Some(ar) = audioreader.as_mut() {
+            match ar.read_chunk(config.timing.chunk_ms as f64) {
+                Ok(Some(buf)) => {
+                    input_backoff.reset();
+                    input = Some(buf);
+                }
+                Ok(None) => {
+                    eprintln!("[Система]: Аудиопоток завершён.");
+                    break;
+                }
+                Err(AudioError::Device(e)) => {
+                    let delay = input_backoff.next_delay();
+                    eprintln!(
+                        "[Система]: Аудиовход прервался ({}), переподключение через {} с.",
+                        e,
+                        delay.as_secs()
+                    );
+                    audioreader = None;
+                    recognizer = None;
+                    spotter = None;
+                    reconnect_in = Some(delay);
+                }
+                Err(e) => {
+                    eprintln!("Ошибка аудиовхода: {}", e);
+                    std::process::exit(1);
                 }
             }
+        }
 
-            // ====================== LISTENING ======================
-            AppState::ListeningQuery => {
-                // -- stop word → immediately back to idle --
-                if config
-                    .assistant
-                    .stop_words
-                    .iter()
-                    .any(|w| text.contains(w.as_str()))
-                {
-                    eprintln!("[Система]: Обнаружено стоп-слово, возврат в режим ожидания.");
-                    state = AppState::Idle;
-                    accumulated_text.clear();
-                    silence_counter = 0;
-                    history = openai::initial_history(&config.assistant.system_prompt);
-                    recognizer = None;
-                    continue;
+        let event = match input {
+            Some(mut buf) => {
+                // ---- remove our own voice from the mic signal ----
+                if let Some(aec) = aec.as_mut() {
+                    aec.process(&mut buf);
                 }
 
-                // -- accumulate finalized text, track silence --
-                if is_final && !text.is_empty() {
-                    if !accumulated_text.is_empty() {
-                        accumulated_text.push(' ');
+                // ---- voice activity ----
+                let speech = vad.as_mut().is_none_or(|v| v.is_speech(&buf));
+
+                // ---- speech recognition ----
+                let skip =
+                    !speech && config.vad.skip_idle_silence && conversation.state() == State::Idle;
+                if spotting {
+                    let spotter = spotter.as_mut().unwrap();
+                    let heard = if skip {
+                        spotter.flush()
+                    } else {
+                        spotter.accept(&buf)
+                    };
+                    heard.map_or(Event::Tick, Event::Final)
+                } else if skip {
+                    // Vosk never hears the silence after the wake word, so flush it
+                    // once at the end of each utterance instead of waiting for a final.
+                    if std::mem::take(&mut utterance_open) {
+                        Event::Final(recognizer.as_mut().unwrap().final_result())
+                    } else {
+                        Event::Tick
                     }
-                    accumulated_text.push_str(&text);
-                    silence_counter = 0;
-                } else if is_final && text.is_empty() && !accumulated_text.is_empty() {
-                    // Empty final after we have text — Vosk detected end of utterance.
-                    // Brief grace period then send to OpenAI.
-                    silence_counter += CONTINUATION_CHUNKS;
-                } else if text.is_empty() {
-                    silence_counter += 1;
                 } else {
-                    // non-empty partial → user is still speaking
-                    silence_counter = 0;
+                    let rec = recognizer.as_mut().unwrap();
+                    if rec.accept_waveform(&buf[..]) {
+                        utterance_open = false;
+                        Event::Final(rec.final_result())
+                    } else if speech {
+                        utterance_open = true;
+                        Event::Partial(rec.partial_result())
+                    } else {
+                        // The partial still holds the last words; it is not new speech.
+                        utterance_open = true;
+                        Event::Tick
+                    }
                 }
+            }
+            None => Event::Tick,
+        };
 
-                // -- have accumulated text & grace period elapsed → send to OpenAI --
-                if !accumulated_text.is_empty() && silence_counter >= CONTINUATION_CHUNKS {
-                    eprintln!("[Вы]: {}", accumulated_text);
-
-                    let tools = tool_mgr.tools();
-                    let response = ai.ask(
-                        &accumulated_text,
-                        &mut history,
-                        &tools,
-                        &mut |name, args| tool_mgr.call_tool(name, args),
-                    );
-                    eprintln!("[Ассистент]: {}", response);
-
-                    // Start non-blocking playback, keep mic alive
-                    recognizer = None;
-                    speak_handle = tts.speak_async(&response);
-                    accumulated_text.clear();
-                    silence_counter = 0;
+        // ---- state machine ----
+        actions.extend(conversation.handle(event));
 
-                    if speak_handle.is_some() {
-                        state = AppState::Speaking;
+        loop {
+            while let Some(action) = actions.pop_front() {
+                match action {
+                    Action::ResetRecognizer => {
+                        recognizer = None;
+                        spotter = None;
                     }
-                }
-
-                // -- silence with no pending text → go idle --
-                if accumulated_text.is_empty() && silence_counter >= SILENCE_TO_IDLE_CHUNKS {
-                    eprintln!("[Система]: Режим ожидания.");
-                    state = AppState::Idle;
-                    history = openai::initial_history(&config.assistant.system_prompt);
-                    recognizer = None;
-                }
-            }
+                    Action::SelectPersona(index) => {
+                        persona = &config.persona[index];
+                        ai.set_model(&persona.model);
+                        if config.persona.len() > 1 {
+                            eprintln!("[Система]: Персона «{}».", persona.name);
+                        }
+                    }
+                    Action::ResetHistory => context.start(persona, &system_prompt(persona)),
+                    Action::Ask(query) => {
+                        // Known commands are handled here, even without network.
+                        // If the tool fails, the LLM gets to deal with it.
+                        if let Some(intent) = intents.find(&query) {
+                            let result = match intent.tool() {
+                                Some(tool) => tool_mgr
+                                    .call_tools(vec![(tool.to_string(), intent.args())], |name| {
+                                        persona.allows_tool(name)
+                                    })
+                                    .remove(0),
+                                None => Ok(String::new()),
+                            };
+                            match result {
+                                Err(e) => eprintln!("[Интент «{}»]: {}", intent.name(), e),
+                                Ok(result) => {
+                                    let reply = intent.reply(&result);
+                                    let history = context.history_mut();
+                                    history.push(openai::user_message(&query));
+                                    history.push(openai::assistant_message(&reply));
+                                    context.touch();
+                                    eprintln!("[Ассистент]: {}", reply);
+                                    actions.extend(conversation.handle(Event::Answer(reply)));
+                                    continue;
+                                }
+                            }
+                        }
 
-            // ====================== SPEAKING ======================
-            AppState::Speaking => {
-                // -- stop word → cancel playback, go idle --
-                if config
-                    .assistant
-                    .stop_words
-                    .iter()
-                    .any(|w| text.contains(w.as_str()))
-                {
-                    eprintln!("[Система]: Обнаружено стоп-слово, остановка воспроизведения.");
-                    if let Some(h) = speak_handle.take() {
-                        h.stop();
+                        let tts = &voices[persona.voice.as_str()];
+                        let tools: Vec<_> = tool_mgr
+                            .tools()
+                            .into_iter()
+                            .filter(|t| persona.allows_tool(t["name"].as_str().unwrap_or_default()))
+                            .collect();
+                        // The model is told about failures in the result text.
+                        let mut call_tools = |calls| {
+                            tool_mgr
+                                .call_tools(calls, |name| persona.allows_tool(name))
+                                .into_iter()
+                                .map(|r| r.unwrap_or_else(|e| e))
+                                .collect()
+                        };
+                        // Each sentence starts playing while the rest is generated.
+                        // Without an output device the reply is only logged.
+                        let handle = match config.openai.stream.then(|| tts.open()) {
+                            Some(Ok(handle)) => Some(handle),
+                            Some(Err(e)) => {
+                                eprintln!("[Система]: {}", e);
+                                None
+                            }
+                            None => None,
+                        };
+                        let response = match &handle {
+                            Some(handle) => ai.ask_streaming(
+                                &query,
+                                context.history_mut(),
+                                &tools,
+                                &mut call_tools,
+                                &mut |sentence| {
+                                    if let Err(e) = tts.append(handle, sentence) {
+                                        eprintln!("[Система]: {}", e);
+                                    }
+                                },
+                            ),
+                            None => ai.ask(&query, context.history_mut(), &tools, &mut call_tools),
+                        };
+                        if response.is_ok() {
+                            speak_handle = handle;
+                        }
+                        match response {
+                            Ok(response) => {
+                                eprintln!("[Ассистент]: {}", response);
+                                context.touch();
+                                actions.extend(conversation.handle(Event::Answer(response)));
+                            }
+                            Err(e) => {
+                                eprintln!("[Система]: Ошибка OpenAI: {}", e);
+                                earcons.play(Cue::Error);
+                                // The raw error is for the log; the user hears a short apology.
+                                let event = match config.openai.apology.as_str() {
+                                    "" => Event::Failed,
+                                    apology => Event::Answer(apology.to_string()),
+                                };
+                                actions.extend(conversation.handle(event));
+                            }
+                        }
                     }
-                    state = AppState::Idle;
-                    accumulated_text.clear();
-                    silence_counter = 0;
-                    history = openai::initial_history(&config.assistant.system_prompt);
-                    recognizer = None;
-                    continue;
+                    // Start non-blocking playback, keep mic alive.
+                    // A streamed reply is already playing.
+                    Action::Speak(text) => {
+                        if speak_handle.is_none() {
+                            match voices[persona.voice.as_str()].speak_async(&text) {
+                                Ok(handle) => speak_handle = handle,
+                                Err(e) => {
+                                    eprintln!("[Система]: {}", e);
+                                    earcons.play(Cue::Error);
+                                }
+                            }
+                        }
+                    }
+                    Action::Announce(text) => {
+                        context.history_mut().push(openai::assistant_message(&text));
+                        context.touch();
+                        if let Some(h) = speak_handle.take() {
+                            h.stop();
+                        }
+                        match voices[persona.voice.as_str()].speak_async(&text) {
+                            Ok(handle) => {
+                                speak_handle = handle;
+                                // Only now are the entries done with; if speaking
+                                // failed they are announced again later.
+                                if let Some(scheduler) = tool_mgr.scheduler() {
+                                    scheduler.finish(&std::mem::take(&mut announcing));
+                                }
+                            }
+                            Err(e) => eprintln!("[Система]: {}", e),
+                        }
+                    }
+                    Action::TruncateReply => {
+                        if let Some(h) = &speak_handle {
+                            openai::truncate_last_reply(context.history_mut(), &h.spoken_text());
+                            context.touch();
+                        }
+                    }
+                    Action::Cue(cue) => {
+                        earcons.play(cue);
+                        // The alarm is heard in full before the announcement.
+                        if cue == Cue::Alarm {
+                            earcons.wait();
+                        }
+                    }
+                    Action::StopPlayback => {
+                        if let Some(h) = speak_handle.take() {
+                            h.stop();
+                        }
+                    }
+                    Action::Log(line) => eprintln!("{}", line),
                 }
+            }
 
-                // -- playback finished → back to listening for follow-up --
-                if speak_handle.as_ref().map_or(true, |h| h.is_finished()) {
-                    speak_handle = None;
-                    state = AppState::ListeningQuery;
-                    silence_counter = 0;
-                    recognizer = None;
-                }
+            // ---- playback finished → follow-up listening ----
+            if conversation.state() == State::Speaking
+                && speak_handle.as_ref().is_none_or(|h| h.is_finished())
+            {
+                speak_handle = None;
+                actions.extend(conversation.handle(Event::PlaybackFinished));
+            } else {
+                break;
             }
         }
+
+        if let Some(delay) = reconnect_in {
+            std::thread::sleep(delay);
+        }
     }
 }
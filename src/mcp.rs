@@ -0,0 +1,419 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::backoff::Backoff;
+use crate::config::McpServerConfig;
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+// ---------------------------------------------------------------------------
+// McpServer — a Model Context Protocol server spoken to over stdio
+// ---------------------------------------------------------------------------
+
+pub struct McpServer {
+    pub name: String,
+    config: McpServerConfig,
+    timeout: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// `None` until the server starts and once it has exited or stopped answering.
+    conn: Option<Connection>,
+    /// Tool definitions from the last `tools/list` (name, description,
+    /// inputSchema); empty until the server has started once.
+    tools: Vec<Value>,
+    /// Whether the server has ever started, for the log.
+    started: bool,
+    backoff: Backoff,
+    /// When a dead server may be started again.
+    restart_at: Instant,
+}
+
+struct Connection {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines read from the server's stdout by a background thread, so that
+    /// a silent server cannot block the caller past the timeout.
+    lines: Receiver<String>,
+    next_id: u64,
+}
+
+enum RpcError {
+    /// The server answered with an error and is still usable.
+    Reply(String),
+    /// The server exited, hung or cannot be written to.
+    Dead(String),
+}
+
+impl McpServer {
+    /// Spawn the server, perform the `initialize` handshake and fetch its
+    /// tools.  A server that fails to start is kept and tried again later,
+    /// like one that has died.
+    pub fn start(config: &McpServerConfig) -> Self {
+        let server = Self {
+            name: config.name.clone(),
+            config: config.clone(),
+            timeout: Duration::from_millis(config.timeout_ms),
+            state: Mutex::new(State {
+                conn: None,
+                tools: Vec::new(),
+                started: false,
+                backoff: Backoff::new(Duration::from_secs(1), Duration::from_secs(60)),
+                restart_at: Instant::now(),
+            }),
+        };
+        let _ = server.ensure_running(&mut server.state.lock().unwrap());
+        server
+    }
+
+    /// Tool definitions of the server, first starting it if it is down and
+    /// due for another attempt.
+    pub fn tools(&self) -> Vec<Value> {
+        let mut state = self.state.lock().unwrap();
+        let _ = self.ensure_running(&mut state);
+        state.tools.clone()
+    }
+
+    /// Whether the server offered a tool named `name` when last listed.
+    pub fn has_tool(&self, name: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.tools.iter().any(|t| t["name"] == name)
+    }
+
+    /// Invoke a tool via `tools/call` and flatten its content into text;
+    /// `Err` if the server could not be reached or reports a tool error.
+    pub fn call_tool(&self, name: &str, args: Value) -> Result<String, String> {
+        let arguments = if args.is_null() { json!({}) } else { args };
+        let result = match self.request(
+            "tools/call",
+            json!({ "name": name, "arguments": arguments }),
+        ) {
+            Ok(r) => r,
+            Err(e) => return Err(format!("Ошибка MCP «{}»: {}", self.name, e)),
+        };
+
+        let text = result["content"]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .map(|item| match item["type"].as_str() {
+                        Some("text") => item["text"].as_str().unwrap_or_default().to_string(),
+                        Some("resource") => item["resource"]["text"]
+                            .as_str()
+                            .map(String::from)
+                            .unwrap_or_else(|| item["resource"]["uri"].to_string()),
+                        Some(other) => format!("[{}]", other),
+                        None => item.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+
+        if result["isError"].as_bool().unwrap_or(false) {
+            Err(format!("Ошибка: {}", text))
+        } else if text.is_empty() {
+            Ok("OK".into())
+        } else {
+            Ok(text)
+        }
+    }
+
+    /// Send a request, first starting the server if it is down.  If it dies
+    /// while handling a request to a running server, it is restarted and the
+    /// request is sent once more.
+    fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        let mut state = self.state.lock().unwrap();
+        let was_running = state.conn.is_some();
+        self.ensure_running(&mut state)?;
+        match self.send(&mut state, method, params.clone()) {
+            Err(RpcError::Dead(e)) if was_running => {
+                self.ensure_running(&mut state).map_err(|_| e)?;
+                self.send(&mut state, method, params).map_err(String::from)
+            }
+            result => result.map_err(String::from),
+        }
+    }
+
+    fn send(&self, state: &mut State, method: &str, params: Value) -> Result<Value, RpcError> {
+        let conn = state.conn.as_mut().unwrap();
+        let result = conn.request(method, params, self.timeout);
+        if let Err(RpcError::Dead(e)) = &result {
+            // Dropping the connection kills the process; it is started again
+            // right away.
+            eprintln!("[MCP «{}»]: {}, сервер будет перезапущен", self.name, e);
+            state.conn = None;
+            state.restart_at = Instant::now();
+        }
+        result
+    }
+
+    /// Start the server unless it is running.  After a failed attempt the
+    /// next one waits for a growing delay.
+    fn ensure_running(&self, state: &mut State) -> Result<(), String> {
+        if state.conn.is_some() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        if now < state.restart_at {
+            return Err(format!(
+                "сервер недоступен, перезапуск через {} с",
+                (state.restart_at - now).as_secs_f32().ceil()
+            ));
+        }
+
+        let opened = Connection::open(&self.config, self.timeout).and_then(|mut conn| {
+            let tools = conn.list_tools(self.timeout)?;
+            Ok((conn, tools))
+        });
+        let action = if state.started {
+            "перезапустить"
+        } else {
+            "запустить"
+        };
+        match opened {
+            Ok((conn, tools)) => {
+                if state.started {
+                    eprintln!("[MCP «{}»]: сервер перезапущен", self.name);
+                } else {
+                    eprintln!(
+                        "[MCP «{}»]: загружено {} инструментов",
+                        self.name,
+                        tools.len()
+                    );
+                }
+                state.conn = Some(conn);
+                state.tools = tools;
+                state.started = true;
+                state.backoff.reset();
+                Ok(())
+            }
+            Err(e) => {
+                let delay = state.backoff.next_delay();
+                state.restart_at = now + delay;
+                eprintln!(
+                    "[MCP «{}»]: не удалось {} ({}), повтор через {} с",
+                    self.name,
+                    action,
+                    e,
+                    delay.as_secs()
+                );
+                Err(format!("не удалось {} сервер: {}", action, e))
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// JSON-RPC over newline-delimited stdio
+// ---------------------------------------------------------------------------
+
+impl Connection {
+    /// Spawn the server and perform the `initialize` handshake.
+    fn open(config: &McpServerConfig, timeout: Duration) -> Result<Self, String> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| format!("{}: {}", config.command, e))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut conn = Self {
+            child,
+            stdin,
+            lines,
+            next_id: 1,
+        };
+        conn.request(
+            "initialize",
+            json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            }),
+            timeout,
+        )?;
+        conn.send(&json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))?;
+        Ok(conn)
+    }
+
+    /// Fetch all tools via `tools/list`, following pagination cursors.
+    fn list_tools(&mut self, timeout: Duration) -> Result<Vec<Value>, String> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(c) => json!({ "cursor": c }),
+                None => json!({}),
+            };
+            let result = self.request("tools/list", params, timeout)?;
+            if let Some(page) = result["tools"].as_array() {
+                tools.extend(page.iter().cloned());
+            }
+            cursor = result["nextCursor"].as_str().map(String::from);
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    fn request(
+        &mut self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, RpcError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let line = self.lines.recv_timeout(remaining).map_err(|e| match e {
+                RecvTimeoutError::Timeout => RpcError::Dead(format!(
+                    "нет ответа на {} за {} мс",
+                    method,
+                    timeout.as_millis()
+                )),
+                RecvTimeoutError::Disconnected => RpcError::Dead("сервер завершился".into()),
+            })?;
+
+            // Skip log output, notifications and responses to other requests.
+            let Ok(msg) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if msg["id"] != json!(id) {
+                continue;
+            }
+            if let Some(err) = msg.get("error") {
+                return Err(RpcError::Reply(format!(
+                    "{} ({})",
+                    err["message"].as_str().unwrap_or("ошибка"),
+                    err["code"]
+                )));
+            }
+            return Ok(msg["result"].clone());
+        }
+    }
+
+    fn send(&mut self, msg: &Value) -> Result<(), RpcError> {
+        writeln!(self.stdin, "{}", msg)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| RpcError::Dead(format!("сервер недоступен: {}", e)))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl From<RpcError> for String {
+    fn from(e: RpcError) -> Self {
+        match e {
+            RpcError::Reply(e) | RpcError::Dead(e) => e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A server that answers every request; `on_call` is the shell code run
+    /// for a `tools/call` after its id has been counted.
+    fn server(prelude: &str, on_call: &str) -> McpServerConfig {
+        let script = format!(
+            r#"{}i=0; while read -r line; do case "$line" in *'"id"'*) i=$((i+1)); case "$line" in *tools/call*) {};; *) echo '{{"jsonrpc":"2.0","id":'$i',"result":{{"tools":[{{"name":"ping"}}]}}}}';; esac;; esac; done"#,
+            prelude, on_call
+        );
+        McpServerConfig {
+            name: "test".into(),
+            command: "sh".into(),
+            args: vec!["-c".into(), script],
+            env: Default::default(),
+            timeout_ms: 2000,
+        }
+    }
+
+    const PONG: &str = r#"echo '{"jsonrpc":"2.0","id":'$i',"result":{"content":[{"type":"text","text":"pong"}]}}'"#;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("voice-home-mcp-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn restarts_server_that_exited_and_retries_the_call() {
+        // Exits right after answering each call.
+        let server = McpServer::start(&server("", &format!("{}; exit 0", PONG)));
+        assert_eq!(server.tools().len(), 1);
+        assert!(server.has_tool("ping"));
+
+        for _ in 0..3 {
+            assert_eq!(server.call_tool("ping", Value::Null), Ok("pong".into()));
+        }
+    }
+
+    #[test]
+    fn retries_a_call_only_once() {
+        let log = temp_path("starts");
+        let _ = std::fs::remove_file(&log);
+        // Counts its starts and dies on every call without answering.
+        let config = server(&format!("echo >> {}; ", log.display()), "exit 1");
+        let server = McpServer::start(&config);
+        let starts = || std::fs::read_to_string(&log).unwrap().lines().count();
+        assert_eq!(starts(), 1);
+
+        assert!(server.call_tool("ping", Value::Null).is_err());
+        assert_eq!(starts(), 2);
+        let _ = std::fs::remove_file(&log);
+    }
+
+    #[test]
+    fn server_that_failed_to_start_is_tried_again() {
+        let marker = temp_path("marker");
+        let _ = std::fs::remove_file(&marker);
+        // Fails the first time only.
+        let prelude = format!("[ -e {0} ] || {{ touch {0}; exit 1; }}; ", marker.display());
+        let server = McpServer::start(&server(&prelude, PONG));
+        assert!(server.tools().is_empty());
+        assert!(
+            server
+                .call_tool("ping", Value::Null)
+                .unwrap_err()
+                .contains("перезапуск через")
+        );
+
+        std::thread::sleep(Duration::from_millis(1100));
+        assert_eq!(server.tools().len(), 1);
+        assert_eq!(server.call_tool("ping", Value::Null), Ok("pong".into()));
+        let _ = std::fs::remove_file(&marker);
+    }
+}
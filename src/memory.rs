@@ -0,0 +1,309 @@
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::config::MemoryConfig;
+
+/// Query words are compared by their first letters so that Russian word
+/// endings do not matter («компьютер» finds «компьютера»).
+const STEM_CHARS: usize = 5;
+
+// ---------------------------------------------------------------------------
+// Memory — facts the assistant keeps between conversations
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Fact {
+    id: u64,
+    text: String,
+    /// Unix time the fact was remembered.
+    created: u64,
+}
+
+/// A small JSON store of facts the model reads and writes through the
+/// `remember_fact`, `recall_facts` and `forget_fact` tools.
+pub struct Memory {
+    path: String,
+    prompt_facts: usize,
+    facts: Mutex<Vec<Fact>>,
+}
+
+impl Memory {
+    /// Load the facts saved at `path`; a missing file is an empty memory.
+    pub fn load(config: &MemoryConfig) -> Result<Self, String> {
+        let facts = match std::fs::read_to_string(&config.path) {
+            Ok(json) => {
+                serde_json::from_str(&json).map_err(|e| format!("{}: {}", config.path, e))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("{}: {}", config.path, e)),
+        };
+        Ok(Self {
+            path: config.path.clone(),
+            prompt_facts: config.prompt_facts,
+            facts: Mutex::new(facts),
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.facts.lock().unwrap().len()
+    }
+
+    /// Tool definitions in the same format as `ToolManager::tools`.
+    pub fn tools() -> Vec<Value> {
+        vec![
+            json!({
+                "name": "remember_fact",
+                "description": "Запомнить факт о доме, его жителях или их предпочтениях надолго, \
+                    например MAC-адрес компьютера или любимую музыку. Один факт — одно короткое утверждение.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "fact": { "type": "string", "description": "Факт, самодостаточный без контекста разговора" },
+                    },
+                    "required": ["fact"],
+                    "additionalProperties": false,
+                }
+            }),
+            json!({
+                "name": "recall_facts",
+                "description": "Найти запомненные факты по словам запроса; без запроса — все факты.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "Слова для поиска" },
+                    },
+                    "required": [],
+                    "additionalProperties": false,
+                }
+            }),
+            json!({
+                "name": "forget_fact",
+                "description": "Забыть факт по его номеру (из recall_facts или системного сообщения).",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "integer", "description": "Номер факта" },
+                    },
+                    "required": ["id"],
+                    "additionalProperties": false,
+                }
+            }),
+        ]
+    }
+
+    /// Execute a memory tool; `None` if `name` is not one of them.
+    pub fn call_tool(&self, name: &str, args: &Value) -> Option<Result<String, String>> {
+        let result = match name {
+            "remember_fact" => match args["fact"].as_str().map(str::trim) {
+                Some(text) if !text.is_empty() => Ok(self.remember(text)),
+                _ => Err("Ошибка: не задан fact".into()),
+            },
+            "recall_facts" => Ok(self.recall(args["query"].as_str().unwrap_or_default())),
+            "forget_fact" => match args["id"].as_u64() {
+                Some(id) => self.forget(id),
+                None => Err("Ошибка: не задан id".into()),
+            },
+            _ => return None,
+        };
+        let (Ok(text) | Err(text)) = &result;
+        eprintln!("[Память]: {} {} → {}", name, args, text);
+        Some(result)
+    }
+
+    /// `system_prompt` followed by the most recent facts.
+    pub fn extend_prompt(&self, system_prompt: &str) -> String {
+        let facts = self.facts.lock().unwrap();
+        if facts.is_empty() || self.prompt_facts == 0 {
+            return system_prompt.to_string();
+        }
+        let shown = &facts[facts.len().saturating_sub(self.prompt_facts)..];
+        let mut prompt = format!("{}\n\nЗапомненные факты:", system_prompt);
+        for fact in shown {
+            prompt.push_str(&format!("\n[{}] {}", fact.id, fact.text));
+        }
+        if shown.len() < facts.len() {
+            prompt.push_str(&format!(
+                "\nИ ещё {} более старых — ищи их через recall_facts.",
+                facts.len() - shown.len()
+            ));
+        }
+        prompt
+    }
+
+    fn remember(&self, text: &str) -> String {
+        let mut facts = self.facts.lock().unwrap();
+        if let Some(fact) = facts
+            .iter()
+            .find(|f| f.text.to_lowercase() == text.to_lowercase())
+        {
+            return format!("Уже известно [{}]", fact.id);
+        }
+        let id = facts.iter().map(|f| f.id).max().unwrap_or(0) + 1;
+        facts.push(Fact {
+            id,
+            text: text.to_string(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        });
+        match self.save(&facts) {
+            Ok(()) => format!("Запомнено [{}]", id),
+            Err(e) => format!("Запомнено [{}] до перезапуска: {}", id, e),
+        }
+    }
+
+    fn recall(&self, query: &str) -> String {
+        let facts = self.facts.lock().unwrap();
+        let stems: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.chars().count() >= 3)
+            .map(|w| w.to_lowercase().chars().take(STEM_CHARS).collect())
+            .collect();
+        let found: Vec<Value> = facts
+            .iter()
+            .filter(|f| {
+                let text = f.text.to_lowercase();
+                stems.is_empty() || stems.iter().any(|s| text.contains(s.as_str()))
+            })
+            .map(|f| json!({ "id": f.id, "fact": f.text }))
+            .collect();
+        if found.is_empty() {
+            "Ничего не найдено".into()
+        } else {
+            Value::Array(found).to_string()
+        }
+    }
+
+    fn forget(&self, id: u64) -> Result<String, String> {
+        let mut facts = self.facts.lock().unwrap();
+        let Some(i) = facts.iter().position(|f| f.id == id) else {
+            return Err(format!("Ошибка: факта [{}] нет", id));
+        };
+        facts.remove(i);
+        Ok(match self.save(&facts) {
+            Ok(()) => format!("Забыто [{}]", id),
+            Err(e) => format!("Забыто [{}] до перезапуска: {}", id, e),
+        })
+    }
+
+    fn save(&self, facts: &[Fact]) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(facts).map_err(|e| e.to_string())?;
+        crate::persist::write_atomic(&self.path, &json).map_err(|e| {
+            eprintln!("[Память]: Не удалось сохранить {}: {}", self.path, e);
+            e.to_string()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(prompt_facts: usize) -> (Memory, String) {
+        let path = std::env::temp_dir()
+            .join(format!(
+                "voice-home-memory-{}-{}.json",
+                std::process::id(),
+                prompt_facts
+            ))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        let config: MemoryConfig = toml::from_str(&format!(
+            "enabled = true\npath = {:?}\nprompt_facts = {}",
+            path, prompt_facts
+        ))
+        .unwrap();
+        (Memory::load(&config).unwrap(), path)
+    }
+
+    /// The result text, whether or not the call failed.
+    fn call(memory: &Memory, name: &str, args: Value) -> String {
+        memory.call_tool(name, &args).unwrap().unwrap_or_else(|e| e)
+    }
+
+    #[test]
+    fn facts_are_remembered_once_and_survive_a_restart() {
+        let (m, path) = memory(1);
+        assert_eq!(
+            call(&m, "remember_fact", json!({ "fact": "Кошку зовут Мурка" })),
+            "Запомнено [1]"
+        );
+        assert_eq!(
+            call(&m, "remember_fact", json!({ "fact": "кошку зовут мурка" })),
+            "Уже известно [1]"
+        );
+        assert!(call(&m, "remember_fact", json!({ "fact": " " })).starts_with("Ошибка"));
+        assert!(m.call_tool("set_timer", &json!({})).is_none());
+
+        let config: MemoryConfig =
+            toml::from_str(&format!("enabled = true\npath = {:?}", path)).unwrap();
+        assert_eq!(Memory::load(&config).unwrap().count(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn recall_ignores_russian_endings() {
+        let (m, path) = memory(2);
+        call(
+            &m,
+            "remember_fact",
+            json!({ "fact": "MAC компьютера 1: 00:11:22:33:44:55" }),
+        );
+        call(
+            &m,
+            "remember_fact",
+            json!({ "fact": "Любимая музыка — джаз" }),
+        );
+
+        let found = call(&m, "recall_facts", json!({ "query": "компьютеры" }));
+        assert!(
+            found.contains("00:11:22") && !found.contains("джаз"),
+            "{}",
+            found
+        );
+        // Short words are not searched for: «на» would match almost anything.
+        let found = call(&m, "recall_facts", json!({ "query": "музыку на" }));
+        assert!(
+            found.contains("джаз") && !found.contains("MAC"),
+            "{}",
+            found
+        );
+        assert_eq!(
+            call(&m, "recall_facts", json!({ "query": "погода" })),
+            "Ничего не найдено"
+        );
+        assert!(call(&m, "recall_facts", json!({})).contains("MAC"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn forgotten_facts_are_gone() {
+        let (m, path) = memory(3);
+        call(&m, "remember_fact", json!({ "fact": "Кошку зовут Мурка" }));
+        assert_eq!(call(&m, "forget_fact", json!({ "id": 1 })), "Забыто [1]");
+        assert!(call(&m, "forget_fact", json!({ "id": 1 })).starts_with("Ошибка"));
+        assert!(call(&m, "forget_fact", json!({})).starts_with("Ошибка"));
+        assert_eq!(m.count(), 0);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn prompt_gets_the_most_recent_facts() {
+        let (m, path) = memory(2);
+        assert_eq!(m.extend_prompt("SYS"), "SYS");
+        for fact in ["Первый", "Второй", "Третий"] {
+            call(&m, "remember_fact", json!({ "fact": fact }));
+        }
+        let prompt = m.extend_prompt("SYS");
+        assert!(prompt.starts_with("SYS\n\nЗапомненные факты:"));
+        assert!(!prompt.contains("Первый"));
+        assert!(prompt.contains("[2] Второй") && prompt.contains("[3] Третий"));
+        assert!(prompt.contains("И ещё 1 более старых"));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,14 +1,37 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
 use async_openai::Client;
+use async_openai::config::OpenAIConfig;
+use async_openai::error::{ApiError, OpenAIError};
 use async_openai::types::chat::{
-    ChatCompletionMessageToolCalls, ChatCompletionRequestAssistantMessageArgs,
+    ChatCompletionMessageToolCall, ChatCompletionMessageToolCalls,
+    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestAssistantMessageContent,
     ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
-    ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs, ChatCompletionTool,
-    ChatCompletionTools, CreateChatCompletionRequestArgs, FunctionObjectArgs,
+    ChatCompletionRequestSystemMessageContent, ChatCompletionRequestToolMessageArgs,
+    ChatCompletionRequestToolMessageContent, ChatCompletionRequestUserMessageArgs,
+    ChatCompletionRequestUserMessageContent, ChatCompletionResponseStream, ChatCompletionTool,
+    ChatCompletionTools, CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
+    FunctionCall, FunctionObjectArgs,
 };
-use serde_json::Value;
+use backoff::ExponentialBackoffBuilder;
+use futures::StreamExt;
+use reqwest::header::HeaderName;
+use serde_json::{Value, json};
+use tokio::time::timeout;
+
+use crate::backoff::Backoff;
+use crate::config::{HistoryStrategy, OpenAiConfig};
 
 pub type Message = ChatCompletionRequestMessage;
 
+/// Marks the system message holding the summary of forgotten turns.
+const SUMMARY_PREFIX: &str = "Краткое содержание начала разговора: ";
+const SUMMARIZE_PROMPT: &str = "Сожми разговор ниже в короткую заметку, по которой его можно \
+продолжить: о чём просил пользователь, что было сделано и какие факты прозвучали. \
+Не более пяти предложений, без вступлений.";
+
 pub fn system_message(content: &str) -> Message {
     ChatCompletionRequestSystemMessageArgs::default()
         .content(content)
@@ -17,49 +40,190 @@ pub fn system_message(content: &str) -> Message {
         .into()
 }
 
+pub fn user_message(content: &str) -> Message {
+    ChatCompletionRequestUserMessageArgs::default()
+        .content(content)
+        .build()
+        .unwrap()
+        .into()
+}
+
+pub fn assistant_message(content: &str) -> Message {
+    ChatCompletionRequestAssistantMessageArgs::default()
+        .content(content)
+        .build()
+        .unwrap()
+        .into()
+}
+
 pub fn initial_history(system_prompt: &str) -> Vec<Message> {
     vec![system_message(system_prompt)]
 }
 
+/// Replace the text of the latest assistant reply with the part the user
+/// actually heard before interrupting it.
+pub fn truncate_last_reply(history: &mut [Message], spoken: &str) {
+    let Some(last) = history
+        .iter_mut()
+        .rev()
+        .find(|m| matches!(m, ChatCompletionRequestMessage::Assistant(a) if a.content.is_some()))
+    else {
+        return;
+    };
+    *last = ChatCompletionRequestAssistantMessageArgs::default()
+        .content(format!("{} …", spoken.trim()).trim_start())
+        .build()
+        .unwrap()
+        .into();
+}
+
 // ---------------------------------------------------------------------------
 // OpenAi — wraps async-openai with a blocking interface
 // ---------------------------------------------------------------------------
 
 pub struct OpenAi {
-    client: Client<async_openai::config::OpenAIConfig>,
+    /// The configured endpoint followed by its fallbacks, tried in order.
+    endpoints: Vec<Endpoint>,
+    timeout: Duration,
+    retries: u32,
+    retry_delay: Duration,
+    max_tool_rounds: u32,
+    max_tool_calls: usize,
+    max_history_messages: usize,
+    max_history_tokens: usize,
+    history_strategy: HistoryStrategy,
+}
+
+struct Endpoint {
+    client: Client<OpenAIConfig>,
     model: String,
+    /// Shown in logs, e.g. `gpt-4o-mini (http://host/v1)`.
+    label: String,
+}
+
+/// How a failed request should be handled.
+enum Failure {
+    /// Worth repeating against the same endpoint (network error, timeout,
+    /// rate limit, server error).
+    Retry(String),
+    /// This endpoint cannot answer; try the next one.
+    NextEndpoint(String),
+    /// No endpoint will accept this request.
+    Abort(String),
+}
+
+impl From<OpenAIError> for Failure {
+    fn from(e: OpenAIError) -> Self {
+        match e {
+            OpenAIError::Reqwest(_) | OpenAIError::StreamError(_) => Failure::Retry(e.to_string()),
+            OpenAIError::InvalidArgument(_) => Failure::Abort(e.to_string()),
+            OpenAIError::ApiError(ref api) if is_transient(api) => Failure::Retry(e.to_string()),
+            // Other API errors (bad key, unknown model, exhausted quota) and
+            // unreadable responses
+            _ => Failure::NextEndpoint(e.to_string()),
+        }
+    }
+}
+
+/// Rate limits (429, unless the quota is used up) and server errors (5xx)
+/// pass with time.  The client does not keep the HTTP status: 5xx bodies
+/// arrive as a bare message without type, code or param.
+fn is_transient(e: &ApiError) -> bool {
+    let kinds = [e.r#type.as_deref(), e.code.as_deref()];
+    if kinds.contains(&Some("insufficient_quota")) {
+        return false;
+    }
+    let rate_limited = kinds
+        .iter()
+        .flatten()
+        .any(|k| k.contains("rate_limit") || matches!(*k, "requests" | "tokens" | "server_error"));
+    rate_limited || (e.r#type.is_none() && e.code.is_none() && e.param.is_none())
 }
 
 impl OpenAi {
-    /// Create a new instance.  Reads `OPENAI_API_KEY` from the environment.
-    pub fn new(model: &str) -> Self {
-        let client = Client::new();
-        Self {
-            client,
-            model: model.into(),
+    /// Create a new instance for the configured (possibly local) endpoint
+    /// and its fallbacks.  The API key is read from `api_key_file` if set,
+    /// otherwise from `api_key_env`.
+    pub fn new(config: &OpenAiConfig) -> Result<Self, String> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let retry_delay = Duration::from_millis(config.retry_delay_ms);
+
+        let mut endpoints = vec![Endpoint::new(
+            &config.model,
+            config.base_url.as_deref(),
+            read_api_key(config.api_key_file.as_deref(), &config.api_key_env)?,
+            config.organization.as_deref(),
+            &config.headers,
+        )?];
+        for fallback in &config.fallback {
+            let api_key = if fallback.api_key_file.is_some() || fallback.api_key_env.is_some() {
+                read_api_key(
+                    fallback.api_key_file.as_deref(),
+                    fallback
+                        .api_key_env
+                        .as_deref()
+                        .unwrap_or(&config.api_key_env),
+                )?
+            } else {
+                read_api_key(config.api_key_file.as_deref(), &config.api_key_env)?
+            };
+            endpoints.push(Endpoint::new(
+                &fallback.model,
+                fallback.base_url.as_deref().or(config.base_url.as_deref()),
+                api_key,
+                fallback
+                    .organization
+                    .as_deref()
+                    .or(config.organization.as_deref()),
+                fallback.headers.as_ref().unwrap_or(&config.headers),
+            )?);
         }
+
+        if config.base_url.is_some() || endpoints.len() > 1 {
+            let labels: Vec<&str> = endpoints.iter().map(|e| e.label.as_str()).collect();
+            eprintln!("[OpenAI]: {}", labels.join(" → "));
+        }
+
+        Ok(Self {
+            endpoints,
+            timeout,
+            retries: config.retries,
+            retry_delay,
+            max_tool_rounds: config.max_tool_rounds,
+            max_tool_calls: config.max_tool_calls_per_turn,
+            max_history_messages: config.max_history_messages,
+            max_history_tokens: config.max_history_tokens,
+            history_strategy: config.history_strategy,
+        })
     }
 
-    /// Send a user query and return the assistant's text reply (blocking).
-    /// Tool calls are dispatched via `execute_tool(name, args) -> result`.
+    /// Use `model` for subsequent requests (e.g. when another persona is woken).
+    /// Fallback endpoints keep their own models.
+    pub fn set_model(&mut self, model: &str) {
+        self.endpoints[0].model = model.to_string();
+    }
+
+    /// Send a user query and return the assistant's text reply (blocking),
+    /// or the error if the endpoint could not be reached.
+    /// The tool calls of each reply are dispatched together via
+    /// `execute_tools([(name, args)]) -> [result]`.
     pub fn ask(
         &self,
         query: &str,
         history: &mut Vec<Message>,
         tools_json: &[Value],
-        execute_tool: &mut impl FnMut(&str, Value) -> String,
-    ) -> String {
-        let user_msg: Message = ChatCompletionRequestUserMessageArgs::default()
-            .content(query)
-            .build()
-            .unwrap()
-            .into();
-        history.push(user_msg);
+        execute_tools: &mut impl FnMut(Vec<(String, Value)>) -> Vec<String>,
+    ) -> Result<String, String> {
+        history.push(user_message(query));
 
         let tools = Self::convert_tools(tools_json);
 
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async { self.ask_loop(history, &tools, execute_tool).await })
+        let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+        let result = rt.block_on(async { self.ask_loop(history, &tools, execute_tools).await });
+        if result.is_err() {
+            abandon_query(history);
+        }
+        result
     }
 
     /// Async variant for use inside an existing tokio runtime (e.g. Discord bot).
@@ -68,17 +232,42 @@ impl OpenAi {
         query: &str,
         history: &mut Vec<Message>,
         tools_json: &[Value],
-        execute_tool: &mut impl FnMut(&str, Value) -> String,
-    ) -> String {
-        let user_msg: Message = ChatCompletionRequestUserMessageArgs::default()
-            .content(query)
-            .build()
-            .unwrap()
-            .into();
-        history.push(user_msg);
+        execute_tools: &mut impl FnMut(Vec<(String, Value)>) -> Vec<String>,
+    ) -> Result<String, String> {
+        history.push(user_message(query));
 
         let tools = Self::convert_tools(tools_json);
-        self.ask_loop(history, &tools, execute_tool).await
+        let result = self.ask_loop(history, &tools, execute_tools).await;
+        if result.is_err() {
+            abandon_query(history);
+        }
+        result
+    }
+
+    /// Streaming variant of `ask` (blocking).  The reply is requested with
+    /// chat-completion streaming and every complete sentence is handed to
+    /// `on_sentence` as soon as it arrives.  Returns the full reply text.
+    pub fn ask_streaming(
+        &self,
+        query: &str,
+        history: &mut Vec<Message>,
+        tools_json: &[Value],
+        execute_tools: &mut impl FnMut(Vec<(String, Value)>) -> Vec<String>,
+        on_sentence: &mut impl FnMut(&str),
+    ) -> Result<String, String> {
+        history.push(user_message(query));
+
+        let tools = Self::convert_tools(tools_json);
+
+        let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+        let result = rt.block_on(async {
+            self.ask_stream_loop(history, &tools, execute_tools, on_sentence)
+                .await
+        });
+        if result.is_err() {
+            abandon_query(history);
+        }
+        result
     }
 
     // ------------------------------------------------------------------
@@ -89,59 +278,32 @@ impl OpenAi {
         &self,
         history: &mut Vec<Message>,
         tools: &[ChatCompletionTools],
-        execute_tool: &mut impl FnMut(&str, Value) -> String,
-    ) -> String {
+        execute_tools: &mut impl FnMut(Vec<(String, Value)>) -> Vec<String>,
+    ) -> Result<String, String> {
+        self.fit_history(history).await;
+        let mut rounds = 0;
         loop {
-            let mut req = CreateChatCompletionRequestArgs::default();
-            req.model(&self.model).messages(history.clone());
-            if !tools.is_empty() {
-                req.tools(tools.to_vec());
-            }
-
-            let request = match req.build() {
-                Ok(r) => r,
-                Err(e) => return format!("Ошибка: {}", e),
-            };
-
-            let response = match self.client.chat().create(request).await {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("OpenAI error: {}", e);
-                    return format!("Ошибка OpenAI: {}", e);
-                }
-            };
+            let offered = self.offered_tools(tools, rounds);
+            let messages: &[Message] = history;
+            let response = self
+                .with_retries(async |endpoint| {
+                    let request = build_request(&endpoint.model, messages, offered)?;
+                    match timeout(self.timeout, endpoint.client.chat().create(request)).await {
+                        Ok(result) => result.map_err(Failure::from),
+                        Err(_) => Err(self.timed_out()),
+                    }
+                })
+                .await?;
 
             let choice = &response.choices[0];
 
             // ---- handle tool calls ----
-            if let Some(ref tool_calls) = choice.message.tool_calls {
-                if !tool_calls.is_empty() {
-                    let asst: Message = ChatCompletionRequestAssistantMessageArgs::default()
-                        .tool_calls(tool_calls.clone())
-                        .build()
-                        .unwrap()
-                        .into();
-                    history.push(asst);
-
-                    for tc_variant in tool_calls {
-                        let ChatCompletionMessageToolCalls::Function(tc) = tc_variant else {
-                            continue;
-                        };
-
-                        let args: Value =
-                            serde_json::from_str(&tc.function.arguments).unwrap_or_default();
-                        let result = execute_tool(&tc.function.name, args);
-
-                        let tool_msg: Message = ChatCompletionRequestToolMessageArgs::default()
-                            .tool_call_id(&tc.id)
-                            .content(result)
-                            .build()
-                            .unwrap()
-                            .into();
-                        history.push(tool_msg);
-                    }
-                    continue;
-                }
+            if let Some(ref tool_calls) = choice.message.tool_calls
+                && !tool_calls.is_empty()
+                && !offered.is_empty()
+            {
+                rounds = self.run_tools(history, tool_calls.clone(), execute_tools, rounds);
+                continue;
             }
 
             // ---- plain text response ----
@@ -152,10 +314,350 @@ impl OpenAi {
                 .unwrap()
                 .into();
             history.push(asst);
-            return content;
+            return Ok(content);
+        }
+    }
+
+    async fn ask_stream_loop(
+        &self,
+        history: &mut Vec<Message>,
+        tools: &[ChatCompletionTools],
+        execute_tools: &mut impl FnMut(Vec<(String, Value)>) -> Vec<String>,
+        on_sentence: &mut impl FnMut(&str),
+    ) -> Result<String, String> {
+        self.fit_history(history).await;
+        let on_sentence = RefCell::new(on_sentence);
+        let mut rounds = 0;
+        loop {
+            let offered = self.offered_tools(tools, rounds);
+            // The whole stream is one attempt: a reply that breaks off before
+            // anything was spoken is requested again.
+            let messages: &[Message] = history;
+            let (content, pending, tool_calls) = self
+                .with_retries(async |endpoint| {
+                    let request = build_request(&endpoint.model, messages, offered)?;
+                    let stream = endpoint.client.chat().create_stream(request).await?;
+                    self.read_stream(stream, &on_sentence).await
+                })
+                .await?;
+
+            // ---- handle tool calls ----
+            if !tool_calls.is_empty() && !offered.is_empty() {
+                let calls = tool_calls
+                    .into_iter()
+                    .map(ChatCompletionMessageToolCalls::Function)
+                    .collect();
+                rounds = self.run_tools(history, calls, execute_tools, rounds);
+                continue;
+            }
+
+            // ---- plain text response: flush the unterminated tail ----
+            let tail = pending.trim();
+            if !tail.is_empty() {
+                (on_sentence.borrow_mut())(tail);
+            }
+            let asst: Message = ChatCompletionRequestAssistantMessageArgs::default()
+                .content(content.clone())
+                .build()
+                .unwrap()
+                .into();
+            history.push(asst);
+            return Ok(content);
+        }
+    }
+
+    /// Read a streamed reply, handing every complete sentence to
+    /// `on_sentence`.  Returns the text, its unterminated tail and the
+    /// tool calls.  A stream that breaks off after a sentence was spoken
+    /// cannot be retried; its tool calls are never run.
+    async fn read_stream(
+        &self,
+        mut stream: ChatCompletionResponseStream,
+        on_sentence: &RefCell<&mut impl FnMut(&str)>,
+    ) -> Result<(String, String, Vec<ChatCompletionMessageToolCall>), Failure> {
+        let mut content = String::new();
+        let mut pending = String::new();
+        let mut tool_calls: Vec<ChatCompletionMessageToolCall> = Vec::new();
+        let mut spoken = false;
+
+        loop {
+            let chunk = match timeout(self.timeout, stream.next()).await {
+                Ok(Some(Ok(chunk))) => chunk,
+                Ok(None) => break,
+                Ok(Some(Err(e))) if !spoken => return Err(e.into()),
+                Err(_) if !spoken => return Err(self.timed_out()),
+                Ok(Some(Err(e))) => return Err(Failure::Abort(format!("ответ прерван: {}", e))),
+                Err(_) => {
+                    return Err(Failure::Abort(format!(
+                        "ответ прерван: {}",
+                        self.timed_out_message()
+                    )));
+                }
+            };
+            let Some(choice) = chunk.choices.into_iter().next() else {
+                continue;
+            };
+
+            // ---- tool-call deltas: merge fragments by index ----
+            for delta in choice.delta.tool_calls.unwrap_or_default() {
+                let idx = delta.index as usize;
+                if tool_calls.len() <= idx {
+                    tool_calls.resize_with(idx + 1, Default::default);
+                }
+                let tc = &mut tool_calls[idx];
+                if let Some(id) = delta.id {
+                    tc.id = id;
+                }
+                if let Some(func) = delta.function {
+                    if let Some(name) = func.name {
+                        tc.function.name.push_str(&name);
+                    }
+                    if let Some(args) = func.arguments {
+                        tc.function.arguments.push_str(&args);
+                    }
+                }
+            }
+
+            // ---- text deltas: emit finished sentences ----
+            if let Some(text) = choice.delta.content {
+                content.push_str(&text);
+                pending.push_str(&text);
+                for sentence in drain_sentences(&mut pending) {
+                    (on_sentence.borrow_mut())(&sentence);
+                    spoken = true;
+                }
+            }
+        }
+        Ok((content, pending, tool_calls))
+    }
+
+    /// Bring `history` within the configured budget by dropping or
+    /// summarizing its oldest turns.  The system prompt and the current
+    /// query are always kept.
+    async fn fit_history(&self, history: &mut Vec<Message>) {
+        let head = history
+            .iter()
+            .take_while(|m| matches!(m, ChatCompletionRequestMessage::System(_)))
+            .count();
+        let fits = |cut: usize| {
+            let kept = || history[..head].iter().chain(&history[cut..]);
+            (self.max_history_messages == 0 || kept().count() <= self.max_history_messages)
+                && (self.max_history_tokens == 0
+                    || kept().map(estimate_tokens).sum::<usize>() <= self.max_history_tokens)
+        };
+        if fits(head) {
+            return;
+        }
+        // Whole turns go, each starting with a user message, so that tool
+        // calls are never separated from their results.
+        let turns: Vec<usize> = (head..history.len())
+            .filter(|&i| matches!(history[i], ChatCompletionRequestMessage::User(_)))
+            .collect();
+        let Some(&last) = turns.last() else {
+            return;
+        };
+        let cut = turns.iter().copied().find(|&c| fits(c)).unwrap_or(last);
+        if cut <= head {
+            return;
+        }
+
+        if self.history_strategy == HistoryStrategy::Summarize {
+            // An earlier summary is folded into the new one.
+            let previous =
+                (0..head).find(|&i| message_text(&history[i]).starts_with(SUMMARY_PREFIX));
+            let forgotten = previous.into_iter().chain(head..cut).map(|i| &history[i]);
+            match self.summarize(forgotten).await {
+                Ok(summary) => {
+                    let note = system_message(&format!("{}{}", SUMMARY_PREFIX, summary));
+                    history.drain(head..cut);
+                    match previous {
+                        Some(i) => history[i] = note,
+                        None => history.insert(head, note),
+                    }
+                    eprintln!(
+                        "[OpenAI]: {} сообщений истории заменены кратким содержанием.",
+                        cut - head
+                    );
+                    return;
+                }
+                Err(e) => eprintln!("[OpenAI]: Не удалось сжать историю: {}", e),
+            }
+        }
+
+        history.drain(head..cut);
+        eprintln!("[OpenAI]: Из истории удалено {} сообщений.", cut - head);
+    }
+
+    /// Ask the model for a short summary of `messages`.
+    async fn summarize(&self, messages: impl Iterator<Item = &Message>) -> Result<String, String> {
+        let transcript: Vec<String> = messages.map(transcript_line).collect();
+        let request = [
+            system_message(SUMMARIZE_PROMPT),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(transcript.join("\n"))
+                .build()
+                .unwrap()
+                .into(),
+        ];
+        let response = self
+            .with_retries(async |endpoint| {
+                let request = build_request(&endpoint.model, &request, &[])?;
+                match timeout(self.timeout, endpoint.client.chat().create(request)).await {
+                    Ok(result) => result.map_err(Failure::from),
+                    Err(_) => Err(self.timed_out()),
+                }
+            })
+            .await?;
+        let summary = response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .unwrap_or_default();
+        match summary.trim() {
+            "" => Err("пустой ответ".into()),
+            summary => Ok(summary.to_string()),
+        }
+    }
+
+    /// The tools to offer after `rounds` replies with tool calls: none once
+    /// the limit is reached, so that the model has to answer.
+    fn offered_tools<'t>(
+        &self,
+        tools: &'t [ChatCompletionTools],
+        rounds: u32,
+    ) -> &'t [ChatCompletionTools] {
+        if rounds < self.max_tool_rounds {
+            tools
+        } else {
+            &[]
         }
     }
 
+    /// Record a reply with tool calls in `history`, run the calls and add
+    /// their results.  Returns the number of tool rounds used so far, which
+    /// jumps to the limit if the reply asked for too many calls.
+    fn run_tools(
+        &self,
+        history: &mut Vec<Message>,
+        calls: Vec<ChatCompletionMessageToolCalls>,
+        execute_tools: &mut impl FnMut(Vec<(String, Value)>) -> Vec<String>,
+        rounds: u32,
+    ) -> u32 {
+        let asst: Message = ChatCompletionRequestAssistantMessageArgs::default()
+            .tool_calls(calls.clone())
+            .build()
+            .unwrap()
+            .into();
+        history.push(asst);
+
+        let functions: Vec<_> = calls
+            .iter()
+            .filter_map(|c| match c {
+                ChatCompletionMessageToolCalls::Function(tc) => Some(tc),
+                _ => None,
+            })
+            .collect();
+        let allowed = functions.len().min(self.max_tool_calls);
+        let parsed: Vec<Result<(String, Value), String>> = functions[..allowed]
+            .iter()
+            .map(|tc| {
+                let FunctionCall { name, arguments } = &tc.function;
+                parse_arguments(name, arguments).map(|args| (name.clone(), args))
+            })
+            .collect();
+        // Calls with unreadable arguments are answered without running them.
+        let mut executed =
+            execute_tools(parsed.iter().filter_map(|p| p.clone().ok()).collect()).into_iter();
+        let mut results: Vec<String> = parsed
+            .into_iter()
+            .map(|p| match p {
+                Ok(_) => executed.next().unwrap_or_default(),
+                Err(e) => e,
+            })
+            .collect();
+        // Every call needs a result, even the ones that were not run.
+        results.resize(
+            functions.len(),
+            format!(
+                "Ошибка: не более {} вызовов инструментов за раз",
+                self.max_tool_calls
+            ),
+        );
+
+        for (tc, result) in functions.iter().zip(results) {
+            let tool_msg: Message = ChatCompletionRequestToolMessageArgs::default()
+                .tool_call_id(&tc.id)
+                .content(result)
+                .build()
+                .unwrap()
+                .into();
+            history.push(tool_msg);
+        }
+
+        let rounds = if allowed < functions.len() {
+            eprintln!(
+                "[OpenAI]: Запрошено {} вызовов инструментов, выполнено {}.",
+                functions.len(),
+                allowed
+            );
+            self.max_tool_rounds
+        } else {
+            rounds + 1
+        };
+        if rounds >= self.max_tool_rounds {
+            eprintln!("[OpenAI]: Лимит вызовов инструментов, требуется ответ.");
+        }
+        rounds
+    }
+
+    /// Run `attempt` against each endpoint in turn until one succeeds.
+    /// Network errors, timeouts, rate limits and server errors are retried
+    /// on the same endpoint with growing delays; other failures move on to
+    /// the next endpoint.
+    async fn with_retries<T>(
+        &self,
+        attempt: impl AsyncFn(&Endpoint) -> Result<T, Failure>,
+    ) -> Result<T, String> {
+        let mut last_error = String::new();
+        for (i, endpoint) in self.endpoints.iter().enumerate() {
+            if i > 0 {
+                eprintln!("[OpenAI]: Переход на {}.", endpoint.label);
+            }
+            let mut backoff = Backoff::new(self.retry_delay, self.retry_delay * 8);
+            for retry in 0..=self.retries {
+                match attempt(endpoint).await {
+                    Ok(result) => return Ok(result),
+                    Err(Failure::Abort(e)) => return Err(e),
+                    Err(Failure::Retry(e)) if retry < self.retries => {
+                        let delay = backoff.next_delay();
+                        eprintln!(
+                            "[OpenAI]: {}: {}, повтор через {} мс.",
+                            endpoint.label,
+                            e,
+                            delay.as_millis()
+                        );
+                        tokio::time::sleep(delay).await;
+                    }
+                    Err(Failure::Retry(e) | Failure::NextEndpoint(e)) => {
+                        eprintln!("[OpenAI]: {}: {}", endpoint.label, e);
+                        last_error = e;
+                        break;
+                    }
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    fn timed_out(&self) -> Failure {
+        Failure::Retry(self.timed_out_message())
+    }
+
+    fn timed_out_message(&self) -> String {
+        format!("нет ответа за {} мс", self.timeout.as_millis())
+    }
+
     /// Convert raw tool definitions (name, description, inputSchema) to
     /// the async-openai typed representation.
     fn convert_tools(raw_tools: &[Value]) -> Vec<ChatCompletionTools> {
@@ -180,3 +682,294 @@ impl OpenAi {
             .collect()
     }
 }
+
+impl Endpoint {
+    fn new(
+        model: &str,
+        base_url: Option<&str>,
+        api_key: String,
+        organization: Option<&str>,
+        headers: &HashMap<String, String>,
+    ) -> Result<Self, String> {
+        let mut client_config = OpenAIConfig::new().with_api_key(api_key);
+        if let Some(base_url) = base_url {
+            client_config = client_config.with_api_base(base_url.trim_end_matches('/'));
+        }
+        if let Some(org) = organization {
+            client_config = client_config.with_org_id(org);
+        }
+        for (key, value) in headers {
+            let name = HeaderName::from_bytes(key.as_bytes())
+                .map_err(|e| format!("заголовок «{}»: {}", key, e))?;
+            client_config = client_config
+                .with_header(name, value.as_str())
+                .map_err(|e| format!("заголовок «{}»: {}", key, e))?;
+        }
+
+        // Retries are left to `with_retries`, which also moves on to the
+        // fallbacks; the client's own backoff would only stack on top of it.
+        let backoff = ExponentialBackoffBuilder::new()
+            .with_max_elapsed_time(Some(Duration::ZERO))
+            .build();
+
+        Ok(Self {
+            client: Client::with_config(client_config).with_backoff(backoff),
+            model: model.to_string(),
+            label: match base_url {
+                Some(url) => format!("{} ({})", model, url),
+                None => model.to_string(),
+            },
+        })
+    }
+}
+
+fn read_api_key(file: Option<&str>, env: &str) -> Result<String, String> {
+    match file {
+        Some(path) => Ok(std::fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?
+            .trim()
+            .to_string()),
+        None => Ok(std::env::var(env).unwrap_or_default()),
+    }
+}
+
+fn build_request(
+    model: &str,
+    history: &[Message],
+    tools: &[ChatCompletionTools],
+) -> Result<CreateChatCompletionRequest, Failure> {
+    let mut req = CreateChatCompletionRequestArgs::default();
+    req.model(model).messages(history.to_vec());
+    if !tools.is_empty() {
+        req.tools(tools.to_vec());
+    }
+    req.build().map_err(|e| Failure::Abort(e.to_string()))
+}
+
+/// Clean up after a query that got no reply.  Without tool calls it leaves
+/// no trace, so it can simply be repeated.  Tools that already ran stay in
+/// the history with a note, so the model knows their effects on the next turn
+/// instead of running them again.
+fn abandon_query(history: &mut Vec<Message>) {
+    let Some(i) = history
+        .iter()
+        .rposition(|m| matches!(m, ChatCompletionRequestMessage::User(_)))
+    else {
+        return;
+    };
+    if history[i + 1..]
+        .iter()
+        .any(|m| matches!(m, ChatCompletionRequestMessage::Tool(_)))
+    {
+        history.push(system_message(
+            "Ответ на этот запрос не был получен из-за ошибки. Инструменты выше уже выполнены, повторно вызывать их не нужно.",
+        ));
+    } else {
+        history.truncate(i);
+    }
+}
+
+/// Tool arguments as sent by the model; an empty string means none.
+/// Arguments that are not JSON get the same error reply as ones that do
+/// not match the tool's schema.
+fn parse_arguments(name: &str, arguments: &str) -> Result<Value, String> {
+    if arguments.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    serde_json::from_str(arguments).map_err(|e| {
+        eprintln!("[OpenAI]: неверные аргументы {}: {}", name, arguments);
+        json!({
+            "error": "invalid_arguments",
+            "tool": name,
+            "problems": [{ "param": "", "message": format!("аргументы не являются JSON: {}", e) }],
+            "hint": "Передайте аргументы JSON-объектом согласно схеме параметров и вызовите инструмент снова.",
+        })
+        .to_string()
+    })
+}
+
+/// Rough token count: about three characters per token for Russian text,
+/// plus the JSON around it.
+fn estimate_tokens(message: &Message) -> usize {
+    serde_json::to_string(message).map_or(0, |json| json.chars().count() / 3)
+}
+
+/// The text content of a message (empty for tool calls and non-text parts).
+fn message_text(message: &Message) -> &str {
+    use ChatCompletionRequestAssistantMessageContent as Assistant;
+    use ChatCompletionRequestSystemMessageContent as System;
+    use ChatCompletionRequestToolMessageContent as Tool;
+    use ChatCompletionRequestUserMessageContent as User;
+    match message {
+        ChatCompletionRequestMessage::System(m) => match &m.content {
+            System::Text(text) => text,
+            _ => "",
+        },
+        ChatCompletionRequestMessage::User(m) => match &m.content {
+            User::Text(text) => text,
+            _ => "",
+        },
+        ChatCompletionRequestMessage::Assistant(m) => match &m.content {
+            Some(Assistant::Text(text)) => text,
+            _ => "",
+        },
+        ChatCompletionRequestMessage::Tool(m) => match &m.content {
+            Tool::Text(text) => text,
+            _ => "",
+        },
+        _ => "",
+    }
+}
+
+/// One line of the transcript handed to the model for summarizing.
+fn transcript_line(message: &Message) -> String {
+    let text = message_text(message);
+    match message {
+        ChatCompletionRequestMessage::System(_) => text.to_string(),
+        ChatCompletionRequestMessage::User(_) => format!("Пользователь: {}", text),
+        ChatCompletionRequestMessage::Assistant(m) => {
+            let calls: Vec<String> = m
+                .tool_calls
+                .iter()
+                .flatten()
+                .filter_map(|c| match c {
+                    ChatCompletionMessageToolCalls::Function(tc) => {
+                        Some(format!("{}({})", tc.function.name, tc.function.arguments))
+                    }
+                    _ => None,
+                })
+                .collect();
+            if calls.is_empty() {
+                format!("Ассистент: {}", text)
+            } else {
+                format!("Ассистент вызывает {}", calls.join(", "))
+            }
+        }
+        ChatCompletionRequestMessage::Tool(_) => format!("Результат: {}", text),
+        _ => String::new(),
+    }
+}
+
+/// Remove every complete sentence from the front of `buf` and return them.
+/// A sentence ends with `.`, `!`, `?` or `…` followed by whitespace, or with a
+/// newline; the unterminated remainder stays in `buf`.
+fn drain_sentences(buf: &mut String) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = buf.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let boundary = match c {
+            '\n' => true,
+            '.' | '!' | '?' | '…' => chars.peek().is_some_and(|&(_, next)| next.is_whitespace()),
+            _ => false,
+        };
+        if boundary {
+            let end = i + c.len_utf8();
+            let sentence = buf[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence.to_string());
+            }
+            start = end;
+        }
+    }
+    buf.drain(..start);
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentences_are_split_at_terminators() {
+        let mut buf = String::from("Привет! Сейчас 10:30. Свет вкл");
+        assert_eq!(drain_sentences(&mut buf), ["Привет!", "Сейчас 10:30."]);
+        assert_eq!(buf, " Свет вкл");
+    }
+
+    #[test]
+    fn numbers_do_not_end_sentences() {
+        let mut buf = String::from("Температура 21.5 градуса");
+        assert!(drain_sentences(&mut buf).is_empty());
+    }
+
+    fn api_error(r#type: Option<&str>, code: Option<&str>) -> OpenAIError {
+        OpenAIError::ApiError(ApiError {
+            message: "error".into(),
+            r#type: r#type.map(String::from),
+            param: None,
+            code: code.map(String::from),
+        })
+    }
+
+    #[test]
+    fn rate_limits_and_server_errors_are_retried() {
+        for e in [
+            api_error(Some("requests"), Some("rate_limit_exceeded")),
+            api_error(Some("tokens"), None),
+            api_error(Some("server_error"), None),
+            // A 5xx body the client could not parse.
+            api_error(None, None),
+        ] {
+            assert!(matches!(Failure::from(e), Failure::Retry(_)));
+        }
+        for e in [
+            api_error(Some("insufficient_quota"), Some("insufficient_quota")),
+            api_error(Some("invalid_request_error"), Some("invalid_api_key")),
+            api_error(Some("api_error"), Some("model_not_found")),
+        ] {
+            assert!(matches!(Failure::from(e), Failure::NextEndpoint(_)));
+        }
+    }
+
+    #[test]
+    fn failed_query_without_tools_is_forgotten() {
+        let mut history = vec![system_message("prompt"), user_message("привет")];
+        abandon_query(&mut history);
+        assert_eq!(history, [system_message("prompt")]);
+    }
+
+    #[test]
+    fn failed_query_keeps_tools_that_ran() {
+        let call = ChatCompletionMessageToolCalls::Function(ChatCompletionMessageToolCall {
+            id: "1".into(),
+            function: FunctionCall {
+                name: "turn_on".into(),
+                arguments: "{}".into(),
+            },
+        });
+        let asst: Message = ChatCompletionRequestAssistantMessageArgs::default()
+            .tool_calls(vec![call])
+            .build()
+            .unwrap()
+            .into();
+        let result: Message = ChatCompletionRequestToolMessageArgs::default()
+            .tool_call_id("1")
+            .content("OK")
+            .build()
+            .unwrap()
+            .into();
+        let mut history = vec![user_message("включи свет"), asst, result];
+        let before = history.clone();
+        abandon_query(&mut history);
+        assert_eq!(history[..3], before[..]);
+        assert!(matches!(
+            history[3],
+            ChatCompletionRequestMessage::System(_)
+        ));
+    }
+
+    #[test]
+    fn empty_arguments_are_an_empty_object() {
+        assert_eq!(parse_arguments("get_time", " "), Ok(json!({})));
+        assert_eq!(parse_arguments("set", r#"{"a":1}"#), Ok(json!({ "a": 1 })));
+    }
+
+    #[test]
+    fn truncated_arguments_are_rejected() {
+        let error = parse_arguments("set_led_color", r#"{"red": 25"#).unwrap_err();
+        let error: Value = serde_json::from_str(&error).unwrap();
+        assert_eq!(error["error"], "invalid_arguments");
+        assert_eq!(error["tool"], "set_led_color");
+    }
+}
@@ -0,0 +1,32 @@
+use std::fs::File;
+use std::io::Write;
+
+// ---------------------------------------------------------------------------
+// write_atomic — replace a state file without ever leaving it half-written
+// ---------------------------------------------------------------------------
+
+/// Write `contents` to a temporary file next to `path`, then rename it over
+/// `path`.  A crash or power loss leaves either the old or the new file.
+pub fn write_atomic(path: &str, contents: &str) -> std::io::Result<()> {
+    let tmp = format!("{}.tmp", path);
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_file_and_leaves_no_temporary() {
+        let path = std::env::temp_dir().join(format!("voice-home-persist-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_atomic(path, "old").unwrap();
+        write_atomic(path, "new").unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "new");
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// ---------------------------------------------------------------------------
+// Bounded child-process execution
+// ---------------------------------------------------------------------------
+
+pub struct Limits {
+    pub timeout: Duration,
+    pub max_output_bytes: usize,
+    /// Kill the whole process group when the timeout expires; otherwise the
+    /// process is left running in the background and only the wait is abandoned.
+    pub kill_on_timeout: bool,
+}
+
+pub enum Status {
+    Exited(ExitStatus),
+    TimedOut { killed: bool },
+}
+
+pub struct Output {
+    pub status: Status,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// At least one stream produced more than `max_output_bytes`.
+    pub truncated: bool,
+}
+
+#[derive(Default)]
+struct Captured {
+    data: Vec<u8>,
+    total: usize,
+    done: bool,
+}
+
+/// Run `cmd` with stdin closed, capturing at most `max_output_bytes` of each
+/// output stream, and stop waiting once `timeout` has elapsed.
+///
+/// The child gets its own process group so that a timeout also reaches
+/// grandchildren such as the `curl` spawned by `sh -c`.
+pub fn run(cmd: &mut Command, limits: &Limits) -> std::io::Result<Output> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+
+    let stdout = capture(child.stdout.take(), limits.max_output_bytes);
+    let stderr = capture(child.stderr.take(), limits.max_output_bytes);
+    let deadline = Instant::now() + limits.timeout;
+
+    let mut exit = None;
+    let status = loop {
+        if exit.is_none() {
+            exit = child.try_wait()?;
+        }
+        let drained = stdout.lock().unwrap().done && stderr.lock().unwrap().done;
+        if let (Some(status), true) = (exit, drained) {
+            break Status::Exited(status);
+        }
+        if Instant::now() >= deadline {
+            match exit {
+                // Exited, but a background grandchild still holds the pipes.
+                Some(status) => break Status::Exited(status),
+                None if limits.kill_on_timeout => {
+                    // SAFETY: `kill` has no memory-safety requirements.  The
+                    // child was spawned with `process_group(0)`, so it leads a
+                    // group whose id is its pid, and it has not been reaped
+                    // (`try_wait` returned `None`), so that id cannot have been
+                    // reused by an unrelated process group.
+                    unsafe {
+                        libc::kill(-(child.id() as i32), libc::SIGKILL);
+                    }
+                    let _ = child.wait();
+                    break Status::TimedOut { killed: true };
+                }
+                None => {
+                    // Reap it whenever it finishes so it does not linger as a zombie.
+                    std::thread::spawn(move || child.wait());
+                    break Status::TimedOut { killed: false };
+                }
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    let (stdout, stderr) = (stdout.lock().unwrap(), stderr.lock().unwrap());
+    Ok(Output {
+        status,
+        truncated: stdout.total > stdout.data.len() || stderr.total > stderr.data.len(),
+        stdout: stdout.data.clone(),
+        stderr: stderr.data.clone(),
+    })
+}
+
+/// Drain `pipe` on a background thread, keeping only the first `limit` bytes
+/// so a chatty command can neither block on a full pipe nor exhaust memory.
+fn capture(pipe: Option<impl Read + Send + 'static>, limit: usize) -> Arc<Mutex<Captured>> {
+    let captured = Arc::new(Mutex::new(Captured::default()));
+    let Some(mut pipe) = pipe else {
+        captured.lock().unwrap().done = true;
+        return captured;
+    };
+
+    let shared = captured.clone();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = match pipe.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            let mut c = shared.lock().unwrap();
+            let keep = n.min(limit.saturating_sub(c.data.len()));
+            c.data.extend_from_slice(&buf[..keep]);
+            c.total += n;
+        }
+        shared.lock().unwrap().done = true;
+    });
+    captured
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(timeout_ms: u64, max_output_bytes: usize, kill_on_timeout: bool) -> Limits {
+        Limits {
+            timeout: Duration::from_millis(timeout_ms),
+            max_output_bytes,
+            kill_on_timeout,
+        }
+    }
+
+    fn sh(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        cmd
+    }
+
+    /// Whether `pid` still runs; zombies awaiting their reaper count as gone.
+    fn alive(pid: &str) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .is_ok_and(|stat| !stat.rsplit(')').next().unwrap_or("").starts_with(" Z"))
+    }
+
+    #[test]
+    fn reports_exit_status_and_both_streams() {
+        let output = run(
+            &mut sh("echo out; echo err >&2; exit 3"),
+            &limits(5000, 1024, true),
+        )
+        .unwrap();
+        let Status::Exited(status) = output.status else {
+            panic!("timed out");
+        };
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+        assert!(!output.truncated);
+    }
+
+    #[test]
+    fn output_is_truncated_at_the_limit() {
+        let output = run(&mut sh("yes | head -n 100000"), &limits(5000, 1000, true)).unwrap();
+        assert!(matches!(output.status, Status::Exited(s) if s.success()));
+        assert_eq!(output.stdout.len(), 1000);
+        assert!(output.stdout.starts_with(b"y\ny\n"));
+        assert!(output.truncated);
+    }
+
+    #[test]
+    fn timeout_kills_the_process_group() {
+        let started = Instant::now();
+        let output = run(
+            &mut sh("sleep 30 & echo $!; sleep 30"),
+            &limits(300, 1024, true),
+        )
+        .unwrap();
+        assert!(matches!(output.status, Status::TimedOut { killed: true }));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // The grandchild went down with its parent.
+        let grandchild = String::from_utf8(output.stdout).unwrap();
+        let grandchild = grandchild.trim();
+        assert!(!grandchild.is_empty());
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while alive(grandchild) && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        assert!(!alive(grandchild));
+    }
+
+    #[test]
+    fn timeout_without_kill_only_stops_waiting() {
+        let started = Instant::now();
+        let output = run(&mut sh("sleep 2"), &limits(200, 1024, false)).unwrap();
+        assert!(matches!(output.status, Status::TimedOut { killed: false }));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn background_grandchild_does_not_hold_up_the_result() {
+        let started = Instant::now();
+        let output = run(&mut sh("sleep 5 & echo started"), &limits(300, 1024, true)).unwrap();
+        assert!(matches!(output.status, Status::Exited(s) if s.success()));
+        assert_eq!(output.stdout, b"started\n");
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+}
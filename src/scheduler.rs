@@ -0,0 +1,427 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Local, NaiveDateTime, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::config::SchedulerConfig;
+
+/// Longest timer; anything later is a reminder.
+const MAX_TIMER_SECONDS: u64 = 24 * 3600;
+
+// ---------------------------------------------------------------------------
+// Scheduler — timers and reminders announced when they are due
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    Timer,
+    Reminder,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Entry {
+    id: u64,
+    kind: Kind,
+    /// Unix time the entry is due.
+    due: i64,
+    /// Timer label or reminder text.
+    text: String,
+    /// Timer length in seconds, repeated in the announcement.
+    #[serde(default)]
+    seconds: u64,
+}
+
+/// Timers and reminders set through the `set_timer`, `set_reminder`,
+/// `list_timers` and `cancel_timer` tools.  Entries are saved to a JSON
+/// file so that they survive a restart; ones that fell due meanwhile are
+/// announced late rather than lost.
+pub struct Scheduler {
+    path: String,
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Scheduler {
+    /// Load the entries saved at `path`; a missing file means none.
+    pub fn load(config: &SchedulerConfig) -> Result<Self, String> {
+        let entries = match std::fs::read_to_string(&config.path) {
+            Ok(json) => {
+                serde_json::from_str(&json).map_err(|e| format!("{}: {}", config.path, e))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("{}: {}", config.path, e)),
+        };
+        Ok(Self {
+            path: config.path.clone(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Tool definitions in the same format as `ToolManager::tools`.
+    pub fn tools() -> Vec<Value> {
+        vec![
+            json!({
+                "name": "set_timer",
+                "description": "Завести таймер на заданное число секунд. По истечении ассистент сам объявит об этом вслух.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "seconds": { "type": "integer", "description": "Длительность в секундах, не больше суток", "minimum": 1, "maximum": MAX_TIMER_SECONDS },
+                        "label": { "type": "string", "description": "Необязательное название, например «паста»" },
+                    },
+                    "required": ["seconds"],
+                    "additionalProperties": false,
+                }
+            }),
+            json!({
+                "name": "set_reminder",
+                "description": "Напомнить о чём-то в заданное время. Ассистент сам произнесёт напоминание.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "time": { "type": "string", "description": "Местное время «ЧЧ:ММ» (ближайшее такое) или «ГГГГ-ММ-ДД ЧЧ:ММ»" },
+                        "text": { "type": "string", "description": "О чём напомнить" },
+                    },
+                    "required": ["time", "text"],
+                    "additionalProperties": false,
+                }
+            }),
+            json!({
+                "name": "list_timers",
+                "description": "Список заведённых таймеров и напоминаний с оставшимся временем.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {},
+                    "required": [],
+                    "additionalProperties": false,
+                }
+            }),
+            json!({
+                "name": "cancel_timer",
+                "description": "Отменить таймер или напоминание по номеру из list_timers.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "integer", "description": "Номер таймера или напоминания" },
+                    },
+                    "required": ["id"],
+                    "additionalProperties": false,
+                }
+            }),
+        ]
+    }
+
+    /// Execute a scheduler tool; `None` if `name` is not one of them.
+    pub fn call_tool(&self, name: &str, args: &Value) -> Option<Result<String, String>> {
+        let result = match name {
+            "set_timer" => match args["seconds"].as_u64() {
+                Some(seconds) if seconds > MAX_TIMER_SECONDS => Err(format!(
+                    "Ошибка: таймер не длиннее {} секунд, для более долгих используйте set_reminder",
+                    MAX_TIMER_SECONDS
+                )),
+                Some(seconds) if seconds > 0 => {
+                    match Duration::try_seconds(seconds as i64)
+                        .and_then(|d| Local::now().checked_add_signed(d))
+                    {
+                        Some(due) => Ok(self.add(
+                            Kind::Timer,
+                            due,
+                            args["label"].as_str().unwrap_or_default().trim(),
+                            seconds,
+                        )),
+                        None => Err("Ошибка: недопустимое seconds".into()),
+                    }
+                }
+                _ => Err("Ошибка: не задано seconds".into()),
+            },
+            "set_reminder" => match (
+                args["time"].as_str().and_then(parse_time),
+                args["text"].as_str().map(str::trim),
+            ) {
+                (Some(due), _) if due <= Local::now() => Err(format!(
+                    "Ошибка: {} уже прошло",
+                    due.format("%Y-%m-%d %H:%M")
+                )),
+                (Some(due), Some(text)) if !text.is_empty() => {
+                    Ok(self.add(Kind::Reminder, due, text, 0))
+                }
+                (None, _) => Err("Ошибка: time должно быть «ЧЧ:ММ» или «ГГГГ-ММ-ДД ЧЧ:ММ»".into()),
+                _ => Err("Ошибка: не задан text".into()),
+            },
+            "list_timers" => Ok(self.list()),
+            "cancel_timer" => match args["id"].as_u64() {
+                Some(id) => self.cancel(id),
+                None => Err("Ошибка: не задан id".into()),
+            },
+            _ => return None,
+        };
+        let (Ok(text) | Err(text)) = &result;
+        eprintln!("[Таймеры]: {} {} → {}", name, args, text);
+        Some(result)
+    }
+
+    /// The ids of the entries that are due and what to announce.  They stay
+    /// scheduled until `finish` is called once the announcement is playing.
+    pub fn due(&self) -> Option<(Vec<u64>, String)> {
+        let entries = self.entries.lock().unwrap();
+        let now = Local::now().timestamp();
+        let due: Vec<&Entry> = entries.iter().filter(|e| e.due <= now).collect();
+        if due.is_empty() {
+            return None;
+        }
+        let ids = due.iter().map(|e| e.id).collect();
+        let texts: Vec<String> = due.into_iter().map(announcement).collect();
+        Some((ids, texts.join(" ")))
+    }
+
+    /// Remove entries that have been announced.
+    pub fn finish(&self, ids: &[u64]) {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|e| !ids.contains(&e.id));
+        if entries.len() != before {
+            self.save(&entries);
+        }
+    }
+
+    fn add(&self, kind: Kind, due: DateTime<Local>, text: &str, seconds: u64) -> String {
+        let mut entries = self.entries.lock().unwrap();
+        let id = entries.iter().map(|e| e.id).max().unwrap_or(0) + 1;
+        entries.push(Entry {
+            id,
+            kind,
+            due: due.timestamp(),
+            text: text.to_string(),
+            seconds,
+        });
+        self.save(&entries);
+        format!(
+            "Готово [{}], сработает {}",
+            id,
+            due.format("%Y-%m-%d %H:%M:%S")
+        )
+    }
+
+    fn list(&self) -> String {
+        let entries = self.entries.lock().unwrap();
+        if entries.is_empty() {
+            return "Нет таймеров и напоминаний".into();
+        }
+        let now = Local::now().timestamp();
+        let list: Vec<Value> = entries
+            .iter()
+            .map(|e| {
+                json!({
+                    "id": e.id,
+                    "kind": match e.kind {
+                        Kind::Timer => "таймер",
+                        Kind::Reminder => "напоминание",
+                    },
+                    "text": e.text,
+                    "due": local(e.due).format("%Y-%m-%d %H:%M:%S").to_string(),
+                    "remaining_seconds": (e.due - now).max(0),
+                })
+            })
+            .collect();
+        Value::Array(list).to_string()
+    }
+
+    fn cancel(&self, id: u64) -> Result<String, String> {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(i) = entries.iter().position(|e| e.id == id) else {
+            return Err(format!("Ошибка: таймера [{}] нет", id));
+        };
+        entries.remove(i);
+        self.save(&entries);
+        Ok(format!("Отменено [{}]", id))
+    }
+
+    /// A failed save only costs the entries on restart, so it is logged.
+    fn save(&self, entries: &[Entry]) {
+        let result = serde_json::to_string_pretty(entries)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                crate::persist::write_atomic(&self.path, &json).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            eprintln!("[Таймеры]: Не удалось сохранить {}: {}", self.path, e);
+        }
+    }
+}
+
+/// `ЧЧ:ММ` (today, or tomorrow if that has passed) or `ГГГГ-ММ-ДД ЧЧ:ММ`,
+/// local time.
+fn parse_time(text: &str) -> Option<DateTime<Local>> {
+    let text = text.trim();
+    let naive = match NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M") {
+        Ok(naive) => naive,
+        Err(_) => {
+            let time = NaiveTime::parse_from_str(text, "%H:%M").ok()?;
+            let now = Local::now();
+            let today = now.date_naive().and_time(time);
+            if today > now.naive_local() {
+                today
+            } else {
+                today + Duration::days(1)
+            }
+        }
+    };
+    Local.from_local_datetime(&naive).earliest()
+}
+
+fn local(timestamp: i64) -> DateTime<Local> {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .unwrap_or_else(Local::now)
+}
+
+fn announcement(entry: &Entry) -> String {
+    match entry.kind {
+        Kind::Timer => {
+            let length = spoken_duration(entry.seconds);
+            match entry.text.as_str() {
+                "" => format!("Таймер на {} сработал.", length),
+                label => format!("Таймер «{}» на {} сработал.", label, length),
+            }
+        }
+        Kind::Reminder => format!(
+            "Напоминание на {}: {}.",
+            local(entry.due).format("%H:%M"),
+            entry.text.trim_end_matches('.')
+        ),
+    }
+}
+
+/// `seconds` as «1 час 5 минут», in the accusative case that follows «на».
+fn spoken_duration(seconds: u64) -> String {
+    let parts = [
+        (seconds / 3600, ["час", "часа", "часов"]),
+        (seconds % 3600 / 60, ["минуту", "минуты", "минут"]),
+        (seconds % 60, ["секунду", "секунды", "секунд"]),
+    ];
+    let text: Vec<String> = parts
+        .iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, forms)| format!("{} {}", n, plural(*n, forms)))
+        .collect();
+    text.join(" ")
+}
+
+/// Russian noun form for `n`: 1 минуту, 2 минуты, 5 минут.
+fn plural<'a>(n: u64, [one, few, many]: &[&'a str; 3]) -> &'a str {
+    match (n % 10, n % 100) {
+        (_, 11..=14) => many,
+        (1, _) => one,
+        (2..=4, _) => few,
+        _ => many,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plural_follows_russian_rules() {
+        let forms = ["минуту", "минуты", "минут"];
+        for (n, form) in [
+            (1, "минуту"),
+            (2, "минуты"),
+            (4, "минуты"),
+            (5, "минут"),
+            (11, "минут"),
+            (14, "минут"),
+            (21, "минуту"),
+            (22, "минуты"),
+            (111, "минут"),
+            (0, "минут"),
+        ] {
+            assert_eq!(plural(n, &forms), form, "{}", n);
+        }
+    }
+
+    #[test]
+    fn durations_are_spoken_in_parts() {
+        assert_eq!(spoken_duration(60), "1 минуту");
+        assert_eq!(spoken_duration(90), "1 минуту 30 секунд");
+        assert_eq!(spoken_duration(3900), "1 час 5 минут");
+        assert_eq!(spoken_duration(7200 + 2), "2 часа 2 секунды");
+    }
+
+    #[test]
+    fn time_of_day_is_the_next_occurrence() {
+        let now = Local::now();
+        let due = parse_time(&(now + Duration::minutes(5)).format("%H:%M").to_string()).unwrap();
+        assert!(due > now && due <= now + Duration::minutes(5));
+        let due = parse_time(&(now - Duration::minutes(5)).format("%H:%M").to_string()).unwrap();
+        assert!(due > now + Duration::hours(23));
+    }
+
+    #[test]
+    fn full_dates_and_garbage() {
+        let due = parse_time(" 2030-01-02 18:30 ").unwrap();
+        assert_eq!(due.format("%Y-%m-%d %H:%M").to_string(), "2030-01-02 18:30");
+        assert!(parse_time("завтра").is_none());
+        assert!(parse_time("25:00").is_none());
+    }
+
+    #[test]
+    fn huge_timers_are_refused() {
+        let path =
+            std::env::temp_dir().join(format!("voice-home-huge-{}.json", std::process::id()));
+        let scheduler = Scheduler {
+            path: path.to_str().unwrap().into(),
+            entries: Mutex::new(Vec::new()),
+        };
+        for seconds in [
+            json!(MAX_TIMER_SECONDS + 1),
+            json!(u64::MAX),
+            json!(i64::MAX as u64 + 1),
+        ] {
+            let result = scheduler
+                .call_tool("set_timer", &json!({ "seconds": seconds }))
+                .unwrap();
+            assert!(result.is_err(), "{:?}", result);
+        }
+        for seconds in [json!(0), json!(-5), json!("десять")] {
+            let result = scheduler
+                .call_tool("set_timer", &json!({ "seconds": seconds }))
+                .unwrap();
+            assert!(result.is_err(), "{:?}", result);
+        }
+        assert_eq!(scheduler.count(), 0);
+        let result = scheduler
+            .call_tool("set_timer", &json!({ "seconds": MAX_TIMER_SECONDS }))
+            .unwrap()
+            .unwrap();
+        assert!(result.starts_with("Готово"), "{}", result);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn due_entries_stay_until_finished() {
+        let path =
+            std::env::temp_dir().join(format!("voice-home-timers-{}.json", std::process::id()));
+        let scheduler = Scheduler {
+            path: path.to_str().unwrap().into(),
+            entries: Mutex::new(Vec::new()),
+        };
+        scheduler.add(Kind::Timer, Local::now() - Duration::seconds(1), "", 60);
+        scheduler.add(Kind::Timer, Local::now() + Duration::hours(1), "", 3600);
+
+        let (ids, text) = scheduler.due().unwrap();
+        assert_eq!(text, "Таймер на 1 минуту сработал.");
+        // Not announced yet: still due.
+        assert_eq!(scheduler.due().unwrap().0, ids);
+        scheduler.finish(&ids);
+        assert!(scheduler.due().is_none());
+        assert_eq!(scheduler.count(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,31 +1,130 @@
-use crate::config::ToolConfig;
+use crate::config::{McpServerConfig, ParamConfig, ToolConfig, ToolDefaultsConfig, ToolKind};
+use crate::http;
+use crate::mcp::McpServer;
+use crate::memory::Memory;
+use crate::process::{self, Limits, Status};
+use crate::scheduler::Scheduler;
+use regex::Regex;
 use serde_json::{Value, json};
 use std::process::Command;
+use std::thread;
+use std::time::Duration;
 
 pub struct ToolManager {
     tools: Vec<ToolConfig>,
+    defaults: ToolDefaultsConfig,
+    mcp_servers: Vec<McpServer>,
+    memory: Option<Memory>,
+    scheduler: Option<Scheduler>,
 }
 
 impl ToolManager {
-    pub fn new(tools: Vec<ToolConfig>) -> Self {
+    pub fn new(
+        mut tools: Vec<ToolConfig>,
+        defaults: ToolDefaultsConfig,
+        mcp_configs: &[McpServerConfig],
+        memory: Option<Memory>,
+        scheduler: Option<Scheduler>,
+    ) -> Self {
         eprintln!("[Инструменты]: загружено {} инструментов", tools.len());
-        for t in &tools {
+        for t in &mut tools {
             eprintln!("  - {}", t.name);
+            match t.kind {
+                ToolKind::Command if t.command.is_empty() && t.argv.is_empty() => {
+                    eprintln!("    (не задан ни command, ни argv)");
+                }
+                ToolKind::Http if t.url.is_empty() => eprintln!("    (не задан url)"),
+                _ => {}
+            }
+            for (param, cfg) in &mut t.params {
+                let Some(pattern) = &cfg.pattern else {
+                    continue;
+                };
+                match Regex::new(&format!("^(?:{})$", pattern)) {
+                    Ok(regex) => cfg.regex = Some(regex),
+                    Err(e) => eprintln!("    (неверный pattern у «{}»: {})", param, e),
+                }
+            }
         }
-        Self { tools }
+
+        if let Some(memory) = &memory {
+            eprintln!("[Память]: {} фактов", memory.count());
+        }
+        if let Some(scheduler) = &scheduler {
+            eprintln!("[Таймеры]: {} ожидают", scheduler.count());
+        }
+
+        let mut mcp_servers = Vec::new();
+        for cfg in mcp_configs {
+            let server = McpServer::start(cfg);
+            for t in server.tools() {
+                let name = t["name"].as_str().unwrap_or_default();
+                if tools.iter().any(|c| c.name == name) {
+                    eprintln!("  - {} (скрыт одноимённым [[tool]])", name);
+                } else {
+                    eprintln!("  - {}", name);
+                }
+            }
+            mcp_servers.push(server);
+        }
+
+        Self {
+            tools,
+            defaults,
+            mcp_servers,
+            memory,
+            scheduler,
+        }
+    }
+
+    pub fn memory(&self) -> Option<&Memory> {
+        self.memory.as_ref()
+    }
+
+    pub fn scheduler(&self) -> Option<&Scheduler> {
+        self.scheduler.as_ref()
     }
 
     /// Return tool definitions in the format expected by OpenAI (name, description, inputSchema).
+    /// `[[tool]]` entries come first, then the built-in ones; later tools
+    /// with the same name as an earlier one are hidden.
     pub fn tools(&self) -> Vec<Value> {
+        let builtin: Vec<Value> = self
+            .memory
+            .iter()
+            .flat_map(|_| Memory::tools())
+            .chain(self.scheduler.iter().flat_map(|_| Scheduler::tools()))
+            .filter(|t| !self.tools.iter().any(|c| t["name"] == c.name.as_str()))
+            .collect();
+        let mcp_tools = self
+            .mcp_servers
+            .iter()
+            .flat_map(|server| server.tools())
+            .filter(|t| !self.tools.iter().any(|c| t["name"] == c.name.as_str()))
+            .filter(|t| !builtin.iter().any(|b| b["name"] == t["name"]))
+            .collect::<Vec<_>>();
         self.tools
             .iter()
             .map(|t| {
                 let mut properties = json!({});
                 for (name, param) in &t.params {
-                    properties[name] = json!({
+                    let mut schema = json!({
                         "type": param.param_type,
                         "description": param.description,
                     });
+                    if !param.allowed.is_empty() {
+                        schema["enum"] = json!(param.allowed);
+                    }
+                    if let Some(min) = param.minimum {
+                        schema["minimum"] = json!(min);
+                    }
+                    if let Some(max) = param.maximum {
+                        schema["maximum"] = json!(max);
+                    }
+                    if let Some(pattern) = &param.pattern {
+                        schema["pattern"] = json!(pattern);
+                    }
+                    properties[name] = schema;
                 }
                 json!({
                     "name": t.name,
@@ -34,52 +133,600 @@ impl ToolManager {
                         "type": "object",
                         "properties": properties,
                         "required": t.required_params,
+                        "additionalProperties": false,
                     }
                 })
             })
+            .chain(builtin)
+            .chain(mcp_tools)
             .collect()
     }
 
-    /// Execute a tool call by name. Substitutes `{{param}}` placeholders in the
-    /// command template with actual argument values, then runs via `sh -c`.
-    pub fn call_tool(&self, name: &str, args: Value) -> String {
-        let tool = match self.tools.iter().find(|t| t.name == name) {
-            Some(t) => t,
-            None => return format!("Инструмент «{}» не найден", name),
+    /// Execute the tool calls of one model reply and return their results in
+    /// the same order; `Err` holds what to tell the model about a failure.
+    /// Calls that `allowed` rejects are refused.  Calls run in the order the
+    /// model gave them, except that consecutive calls to `parallel_safe`
+    /// tools run concurrently.
+    pub fn call_tools(
+        &self,
+        calls: Vec<(String, Value)>,
+        allowed: impl Fn(&str) -> bool,
+    ) -> Vec<Result<String, String>> {
+        let mut results = vec![Ok(String::new()); calls.len()];
+        let mut batch = Vec::new();
+        for (i, (name, args)) in calls.into_iter().enumerate() {
+            if !allowed(&name) {
+                results[i] = Err(format!("Ошибка: инструмент «{}» недоступен", name));
+            } else if self.is_parallel_safe(&name) {
+                batch.push((i, name, args));
+            } else {
+                self.call_concurrently(std::mem::take(&mut batch), &mut results);
+                results[i] = self.call_tool(&name, args);
+            }
+        }
+        self.call_concurrently(batch, &mut results);
+        results
+    }
+
+    /// Run a batch of `parallel_safe` calls at the same time.
+    fn call_concurrently(
+        &self,
+        batch: Vec<(usize, String, Value)>,
+        results: &mut [Result<String, String>],
+    ) {
+        if batch.len() < 2 {
+            for (i, name, args) in batch {
+                results[i] = self.call_tool(&name, args);
+            }
+            return;
+        }
+        thread::scope(|s| {
+            let running: Vec<_> = batch
+                .into_iter()
+                .map(|(i, name, args)| (i, s.spawn(move || self.call_tool(&name, args))))
+                .collect();
+            for (i, handle) in running {
+                results[i] = handle
+                    .join()
+                    .unwrap_or_else(|_| Err("Ошибка: инструмент аварийно завершился".into()));
+            }
+        });
+    }
+
+    /// MCP tools share one connection per server and are never run in parallel.
+    fn is_parallel_safe(&self, name: &str) -> bool {
+        self.tools
+            .iter()
+            .find(|t| t.name == name)
+            .is_some_and(|t| t.parallel_safe)
+    }
+
+    /// Execute a tool call by name after validating its arguments.
+    pub fn call_tool(&self, name: &str, args: Value) -> Result<String, String> {
+        let Some(tool) = self.tools.iter().find(|t| t.name == name) else {
+            if let Some(result) = self.memory.as_ref().and_then(|m| m.call_tool(name, &args)) {
+                return result;
+            }
+            if let Some(result) = self
+                .scheduler
+                .as_ref()
+                .and_then(|s| s.call_tool(name, &args))
+            {
+                return result;
+            }
+            return self.call_mcp_tool(name, args);
         };
 
-        let mut cmd = tool.command.clone();
-        if let Some(obj) = args.as_object() {
-            for (key, val) in obj {
-                let replacement = match val {
-                    Value::String(s) => s.clone(),
-                    other => other.to_string(),
-                };
-                cmd = cmd.replace(&format!("{{{{{}}}}}", key), &replacement);
+        if let Err(problems) = validate_args(tool, &args) {
+            eprintln!("[Инструмент «{}»]: неверные аргументы {}", name, args);
+            return Err(json!({
+                "error": "invalid_arguments",
+                "tool": name,
+                "problems": problems
+                    .into_iter()
+                    .map(|(param, message)| json!({ "param": param, "message": message }))
+                    .collect::<Vec<_>>(),
+                "hint": "Исправьте аргументы согласно схеме параметров и вызовите инструмент снова.",
+            })
+            .to_string());
+        }
+
+        // Declared but omitted optional params are left out rather than
+        // leaving a literal `{{param}}` in the command.
+        let mut args = args;
+        if let Some(obj) = args.as_object_mut() {
+            for key in tool.params.keys() {
+                obj.entry(key.clone()).or_insert(Value::Null);
             }
         }
 
-        eprintln!("[Инструмент «{}»]: {}", name, cmd);
+        match tool.kind {
+            ToolKind::Command => self.run_command(tool, &args),
+            ToolKind::Http => self.run_http(tool, &args),
+        }
+    }
+
+    /// With `argv` the program is run directly with `{{param}}` placeholders
+    /// substituted per argument; otherwise the `command` template is run via
+    /// `sh -c` with every value shell-quoted (unless the param is `raw`).
+    /// An element of `argv` that is just the placeholder of an omitted param
+    /// is dropped.
+    fn run_command(&self, tool: &ToolConfig, args: &Value) -> Result<String, String> {
+        let name = &tool.name;
+        let mut command = if !tool.argv.is_empty() {
+            let argv: Vec<String> = tool
+                .argv
+                .iter()
+                .filter(|a| !is_omitted(a, args))
+                .map(|a| render(a, args, |_, v| v.to_string()))
+                .collect();
+            eprintln!("[Инструмент «{}»]: {:?}", name, argv);
+            // The program itself can be a placeholder that was left out.
+            let Some((program, rest)) = argv.split_first() else {
+                return Err("Ошибка: не задана программа для запуска".into());
+            };
+            let mut command = Command::new(program);
+            command.args(rest);
+            command
+        } else {
+            let cmd = render(&tool.command, args, |key, v| {
+                if tool.params.get(key).is_some_and(|p| p.raw) {
+                    v.to_string()
+                } else {
+                    shell_quote(v)
+                }
+            });
+            eprintln!("[Инструмент «{}»]: {}", name, cmd);
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(&cmd);
+            command
+        };
+
+        let limits = self.limits(tool);
+        let output = match process::run(&mut command, &limits) {
+            Ok(output) => output,
+            Err(e) => return Err(format!("Ошибка запуска: {}", e)),
+        };
 
-        match Command::new("sh").arg("-c").arg(&cmd).output() {
-            Ok(output) => {
-                let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-                if output.status.success() {
-                    if stdout.is_empty() {
-                        "OK".into()
+        let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let mut result = match output.status {
+            Status::Exited(status) if status.success() => {
+                if stdout.is_empty() {
+                    Ok("OK".into())
+                } else {
+                    Ok(stdout)
+                }
+            }
+            Status::Exited(status) => Err(format!(
+                "Ошибка (код {}): {}",
+                status,
+                if stderr.is_empty() { &stdout } else { &stderr }
+            )),
+            Status::TimedOut { killed } => {
+                eprintln!(
+                    "[Инструмент «{}»]: превышено время ожидания ({} мс)",
+                    name,
+                    limits.timeout.as_millis()
+                );
+                let mut msg = format!(
+                    "Ошибка: превышено время ожидания ({} мс), {}",
+                    limits.timeout.as_millis(),
+                    if killed {
+                        "процесс остановлен"
                     } else {
-                        stdout
+                        "процесс продолжает работу в фоне"
                     }
-                } else {
-                    format!(
-                        "Ошибка (код {}): {}",
-                        output.status,
-                        if stderr.is_empty() { &stdout } else { &stderr }
-                    )
+                );
+                if !stdout.is_empty() {
+                    msg.push_str(&format!(". Частичный вывод: {}", stdout));
+                }
+                Err(msg)
+            }
+        };
+        if output.truncated {
+            let (Ok(text) | Err(text)) = &mut result;
+            text.push_str(&format!(
+                "\n[вывод обрезан до {} байт]",
+                limits.max_output_bytes
+            ));
+        }
+        result
+    }
+
+    /// Send the request in-process: the URL gets percent-encoded arguments,
+    /// the body JSON-encoded ones.
+    fn run_http(&self, tool: &ToolConfig, args: &Value) -> Result<String, String> {
+        let name = &tool.name;
+        let url = render(&tool.url, args, |_, v| http::url_encode(v));
+        // Every value goes into the body as JSON, omitted params as `null`.
+        let json_args: Value = args
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(k, v)| (k.clone(), json!(v.to_string())))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+        let body = tool
+            .body
+            .as_ref()
+            .map(|b| render(b, &json_args, |_, v| v.to_string()));
+        let headers = tool
+            .headers
+            .iter()
+            .map(|(k, v)| (k.clone(), render(v, args, |_, v| v.to_string())))
+            .collect();
+        eprintln!("[Инструмент «{}»]: {} {}", name, tool.method, url);
+
+        let limits = self.limits(tool);
+        let request = http::Request {
+            method: &tool.method,
+            url: &url,
+            headers,
+            body,
+            timeout: limits.timeout,
+            max_body_bytes: limits.max_output_bytes,
+        };
+        let response = match http::send(&request) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("[Инструмент «{}»]: {}", name, e);
+                return Err(format!(
+                    "Ошибка: устройство недоступно ({} {}): {}",
+                    tool.method, url, e
+                ));
+            }
+        };
+
+        let ok = if tool.expect_status.is_empty() {
+            (200..300).contains(&response.status)
+        } else {
+            tool.expect_status.contains(&response.status)
+        };
+        if !ok {
+            return Err(format!(
+                "Ошибка (HTTP {}): {}",
+                response.status, response.body
+            ));
+        }
+
+        let mut result = match &tool.extract {
+            Some(path) => match serde_json::from_str::<Value>(&response.body) {
+                Ok(json) => match http::extract(&json, path) {
+                    Some(Value::String(s)) => Ok(s.clone()),
+                    Some(v) => Ok(v.to_string()),
+                    None => Err(format!("Ошибка: в ответе нет {}: {}", path, response.body)),
+                },
+                Err(_) => Ok(response.body),
+            },
+            None if response.body.is_empty() => Ok("OK".into()),
+            None => Ok(response.body),
+        };
+        if response.truncated {
+            let (Ok(text) | Err(text)) = &mut result;
+            text.push_str(&format!(
+                "\n[вывод обрезан до {} байт]",
+                limits.max_output_bytes
+            ));
+        }
+        result
+    }
+
+    /// Route a call to the MCP server that advertised the tool; the server
+    /// validates the arguments against its own schema.
+    fn call_mcp_tool(&self, name: &str, args: Value) -> Result<String, String> {
+        let Some(server) = self.mcp_servers.iter().find(|s| s.has_tool(name)) else {
+            return Err(format!("Инструмент «{}» не найден", name));
+        };
+
+        eprintln!("[MCP «{}»]: {} {}", server.name, name, args);
+        let mut result = server.call_tool(name, args);
+        let limit = self.defaults.max_output_bytes;
+        let (Ok(text) | Err(text)) = &mut result;
+        if text.len() > limit {
+            let mut cut = limit;
+            while !text.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            text.truncate(cut);
+            text.push_str(&format!("\n[вывод обрезан до {} байт]", limit));
+        }
+        result
+    }
+
+    fn limits(&self, tool: &ToolConfig) -> Limits {
+        Limits {
+            timeout: Duration::from_millis(tool.timeout_ms.unwrap_or(self.defaults.timeout_ms)),
+            max_output_bytes: tool
+                .max_output_bytes
+                .unwrap_or(self.defaults.max_output_bytes),
+            kill_on_timeout: tool
+                .kill_on_timeout
+                .unwrap_or(self.defaults.kill_on_timeout),
+        }
+    }
+}
+
+/// Check `args` against the tool's declared params.  Returns every problem as
+/// `(param, message)` so the model can fix all of them in one retry.
+fn validate_args(tool: &ToolConfig, args: &Value) -> Result<(), Vec<(String, String)>> {
+    let empty = serde_json::Map::new();
+    let obj = match args {
+        Value::Object(obj) => obj,
+        Value::Null => &empty,
+        _ => {
+            return Err(vec![(
+                String::new(),
+                "аргументы должны быть объектом".into(),
+            )]);
+        }
+    };
+
+    let mut problems = Vec::new();
+    for required in &tool.required_params {
+        if !obj.contains_key(required) {
+            problems.push((required.clone(), "обязательный параметр отсутствует".into()));
+        }
+    }
+    for (key, value) in obj {
+        match tool.params.get(key) {
+            Some(param) => {
+                if let Err(message) = validate_value(param, value) {
+                    problems.push((key.clone(), message));
                 }
             }
-            Err(e) => format!("Ошибка запуска: {}", e),
+            None => problems.push((key.clone(), "неизвестный параметр".into())),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+fn validate_value(param: &ParamConfig, value: &Value) -> Result<(), String> {
+    let type_ok = match param.param_type.as_str() {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    };
+    if !type_ok {
+        return Err(format!(
+            "ожидается тип {}, получено {}",
+            param.param_type, value
+        ));
+    }
+
+    if !param.allowed.is_empty() && !param.allowed.contains(value) {
+        return Err(format!(
+            "допустимые значения: {}",
+            Value::from(param.allowed.clone())
+        ));
+    }
+
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = param.minimum.filter(|&min| n < min) {
+            return Err(format!("значение {} меньше минимума {}", n, min));
+        }
+        if let Some(max) = param.maximum.filter(|&max| n > max) {
+            return Err(format!("значение {} больше максимума {}", n, max));
+        }
+    }
+
+    if let (Some(pattern), Some(s)) = (&param.pattern, value.as_str()) {
+        let Some(regex) = &param.regex else {
+            return Err("неверный pattern в конфигурации".into());
+        };
+        if !regex.is_match(s) {
+            return Err(format!("значение не соответствует шаблону {}", pattern));
         }
     }
+
+    Ok(())
+}
+
+/// Replace `{{key}}` placeholders in a single pass, so substituted values are
+/// never re-scanned.  `encode(key, value)` produces the inserted text; an
+/// omitted param (`null`) becomes nothing at all, and placeholders without a
+/// matching argument are left untouched.
+fn render(template: &str, args: &Value, encode: impl Fn(&str, &str) -> String) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let key = &after[..end];
+        match args.get(key) {
+            Some(Value::Null) => {}
+            Some(Value::String(v)) => out.push_str(&encode(key, v)),
+            Some(other) => out.push_str(&encode(key, &other.to_string())),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `true` if `template` is a single `{{key}}` for an omitted param.
+fn is_omitted(template: &str, args: &Value) -> bool {
+    template
+        .strip_prefix("{{")
+        .and_then(|t| t.strip_suffix("}}"))
+        .is_some_and(|key| args.get(key).is_some_and(Value::is_null))
+}
+
+/// Quote a value for POSIX `sh`.  Plain tokens are left as is; anything else
+/// is wrapped in single quotes with embedded quotes escaped as `'\''`.
+fn shell_quote(value: &str) -> String {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.,:/@%+=".contains(c));
+    if safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(tool: &str) -> ToolManager {
+        let tool: ToolConfig = toml::from_str(tool).unwrap();
+        ToolManager::new(vec![tool], ToolDefaultsConfig::default(), &[], None, None)
+    }
+
+    #[test]
+    fn calls_keep_their_order_around_parallel_runs() {
+        let log = std::env::temp_dir().join(format!("voice-home-order-{}", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let tool = |name: &str, parallel: bool, sleep: &str| -> ToolConfig {
+            toml::from_str(&format!(
+                r#"
+                name = "{name}"
+                description = ""
+                command = "sleep {sleep}; echo {name} >> {log}"
+                parallel_safe = {parallel}
+                "#,
+                log = log.display(),
+            ))
+            .unwrap()
+        };
+        let tools = ToolManager::new(
+            vec![
+                tool("first", false, "0.1"),
+                tool("slow", true, "0.3"),
+                tool("slower", true, "0.3"),
+                tool("last", false, "0"),
+            ],
+            ToolDefaultsConfig::default(),
+            &[],
+            None,
+            None,
+        );
+
+        let calls = ["first", "slow", "slower", "last"]
+            .map(|name| (name.to_string(), json!({})))
+            .to_vec();
+        let started = std::time::Instant::now();
+        assert_eq!(tools.call_tools(calls, |_| true).len(), 4);
+        let elapsed = started.elapsed();
+
+        let order = std::fs::read_to_string(&log).unwrap();
+        let _ = std::fs::remove_file(&log);
+        let order: Vec<&str> = order.lines().collect();
+        assert_eq!(order.first(), Some(&"first"));
+        assert_eq!(order.last(), Some(&"last"));
+        assert_eq!(order.len(), 4);
+        // The two parallel-safe calls overlapped.
+        assert!(
+            elapsed < std::time::Duration::from_millis(650),
+            "{elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn shell_quote_leaves_plain_tokens() {
+        assert_eq!(shell_quote("00:11:22:aa"), "00:11:22:aa");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("a; rm -rf ~"), "'a; rm -rf ~'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn render_does_not_rescan_values() {
+        let args = json!({ "a": "{{b}}", "b": "x" });
+        assert_eq!(
+            render("{{a}} {{b}} {{c}}", &args, |_, v| v.to_string()),
+            "{{b}} x {{c}}"
+        );
+    }
+
+    #[test]
+    fn pattern_is_checked() {
+        let tools = manager(
+            r#"
+            name = "wake"
+            description = ""
+            argv = ["echo", "{{mac}}"]
+            [params.mac]
+            type = "string"
+            pattern = "[0-9a-f]{2}(:[0-9a-f]{2}){5}"
+            "#,
+        );
+        let error: Value = serde_json::from_str(
+            &tools
+                .call_tool("wake", json!({ "mac": "00:11" }))
+                .unwrap_err(),
+        )
+        .unwrap();
+        assert_eq!(error["error"], "invalid_arguments");
+        assert_eq!(
+            tools.call_tool("wake", json!({ "mac": "00:11:22:33:44:55" })),
+            Ok("00:11:22:33:44:55".into())
+        );
+    }
+
+    #[test]
+    fn omitted_params_are_left_out_of_commands() {
+        let tools = manager(
+            r#"
+            name = "say"
+            description = ""
+            command = "printf '%s|' a {{opt}} b"
+            [params.opt]
+            type = "string"
+            "#,
+        );
+        assert_eq!(tools.call_tool("say", json!({})), Ok("a|b|".into()));
+        assert_eq!(
+            tools.call_tool("say", json!({ "opt": "" })),
+            Ok("a||b|".into())
+        );
+    }
+
+    #[test]
+    fn omitted_params_are_left_out_of_argv() {
+        let tools = manager(
+            r#"
+            name = "say"
+            description = ""
+            argv = ["printf", "%s|", "a", "{{opt}}", "b"]
+            [params.opt]
+            type = "string"
+            "#,
+        );
+        assert_eq!(tools.call_tool("say", json!({})), Ok("a|b|".into()));
+    }
+
+    #[test]
+    fn omitted_program_is_an_error() {
+        let tools = manager(
+            r#"
+            name = "run"
+            description = ""
+            argv = ["{{program}}"]
+            [params.program]
+            type = "string"
+            "#,
+        );
+        assert!(tools.call_tool("run", json!({})).is_err());
+        assert_eq!(
+            tools.call_tool("run", json!({ "program": "true" })),
+            Ok("OK".into())
+        );
+    }
 }
@@ -1,69 +1,219 @@
+use std::cell::RefCell;
+use std::fmt;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 use piper_rs::synth::PiperSpeechSynthesizer;
 use rodio::{OutputStream, Sink, buffer::SamplesBuffer};
 
+use crate::aec::EchoReference;
+use crate::backoff::Backoff;
+
+const SAMPLE_RATE: u32 = 22050;
+/// Playback that has not advanced for this long is treated as finished
+/// (e.g. the output device disappeared mid-reply).
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug)]
+pub enum TtsError {
+    /// The voice model could not be loaded.
+    Model(String),
+    /// Piper failed to synthesize the text.
+    Synthesis(String),
+    /// No audio output device is available.
+    Output(String),
+}
+
+impl fmt::Display for TtsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TtsError::Model(e) => write!(f, "ошибка загрузки модели TTS: {}", e),
+            TtsError::Synthesis(e) => write!(f, "ошибка синтеза речи: {}", e),
+            TtsError::Output(e) => write!(f, "аудиовыход недоступен: {}", e),
+        }
+    }
+}
+
 pub struct SpeakHandle {
     sink: Sink,
     _stream: OutputStream,
+    /// Text and duration of every queued piece, in playback order.
+    segments: RefCell<Vec<(String, Duration)>>,
+    echo: Option<EchoReference>,
+    /// Last observed queue length and position, and when they last changed.
+    progress: RefCell<(usize, Duration, Instant)>,
 }
 
 impl SpeakHandle {
     pub fn is_finished(&self) -> bool {
-        self.sink.empty()
+        if self.sink.empty() {
+            return true;
+        }
+        let mut progress = self.progress.borrow_mut();
+        let now = (self.sink.len(), self.sink.get_pos());
+        if (progress.0, progress.1) != now {
+            *progress = (now.0, now.1, Instant::now());
+            return false;
+        }
+        if progress.2.elapsed() > STALL_TIMEOUT {
+            eprintln!("[Система]: Воспроизведение остановилось, ответ прерван.");
+            return true;
+        }
+        false
     }
 
     pub fn stop(self) {
         self.sink.stop();
     }
+
+    /// Approximate text heard so far: finished pieces in full plus the
+    /// proportional share of the current one, cut back to a word boundary.
+    pub fn spoken_text(&self) -> String {
+        let segments = self.segments.borrow();
+        let current = segments.len().saturating_sub(self.sink.len());
+        let mut spoken: Vec<&str> = segments[..current]
+            .iter()
+            .map(|(t, _)| t.as_str())
+            .collect();
+
+        if let Some((text, duration)) = segments.get(current) {
+            let fraction = (self.sink.get_pos().as_secs_f64() / duration.as_secs_f64()).min(1.0);
+            let mut cut = (text.chars().count() as f64 * fraction) as usize;
+            let chars: Vec<char> = text.chars().collect();
+            while cut > 0 && cut < chars.len() && !chars[cut].is_whitespace() {
+                cut -= 1;
+            }
+            let byte_end = text.char_indices().nth(cut).map_or(text.len(), |(i, _)| i);
+            let partial = text[..byte_end].trim_end();
+            if !partial.is_empty() {
+                spoken.push(partial);
+            }
+        }
+        spoken.join(" ")
+    }
+
+    fn push(&self, text: &str, samples: Vec<f32>) {
+        let duration = Duration::from_secs_f64(samples.len() as f64 / SAMPLE_RATE as f64);
+        self.segments
+            .borrow_mut()
+            .push((text.to_string(), duration));
+        let source = SamplesBuffer::new(1, SAMPLE_RATE, samples);
+        match &self.echo {
+            Some(echo) => self.sink.append(echo.tap(source)),
+            None => self.sink.append(source),
+        }
+    }
 }
 
 pub struct Tts {
     synth: PiperSpeechSynthesizer,
+    echo: Option<EchoReference>,
+    /// Delay before the output device is tried again after a failure.
+    output_backoff: RefCell<Backoff>,
+    output_retry_at: RefCell<Option<Instant>>,
 }
 
 impl Tts {
-    pub fn new(config_path: &str) -> Self {
-        let model =
-            piper_rs::from_config_path(Path::new(config_path)).expect("Ошибка загрузки модели TTS");
-        let synth = PiperSpeechSynthesizer::new(model).expect("Ошибка инициализации TTS");
-        Self { synth }
+    /// `echo` receives a copy of everything played, for echo cancellation.
+    pub fn new(config_path: &str, echo: Option<EchoReference>) -> Result<Self, TtsError> {
+        let model = piper_rs::from_config_path(Path::new(config_path))
+            .map_err(|e| TtsError::Model(format!("{}: {}", config_path, e)))?;
+        let synth = PiperSpeechSynthesizer::new(model)
+            .map_err(|e| TtsError::Model(format!("{}: {}", config_path, e)))?;
+        Ok(Self {
+            synth,
+            echo,
+            output_backoff: RefCell::new(Backoff::new(
+                Duration::from_secs(1),
+                Duration::from_secs(60),
+            )),
+            output_retry_at: RefCell::new(None),
+        })
     }
 
-    fn synthesize(&self, text: &str) -> Vec<f32> {
+    fn synthesize(&self, text: &str) -> Result<Vec<f32>, TtsError> {
         let audio_stream = self
             .synth
             .synthesize_parallel(text.to_string(), None)
-            .expect("Ошибка синтеза речи");
-        audio_stream
-            .into_iter()
-            .flat_map(|result| result.expect("Ошибка синтеза фрагмента").into_vec())
-            .collect()
+            .map_err(|e| TtsError::Synthesis(e.to_string()))?;
+        let mut samples = Vec::new();
+        for chunk in audio_stream {
+            samples.extend(
+                chunk
+                    .map_err(|e| TtsError::Synthesis(e.to_string()))?
+                    .into_vec(),
+            );
+        }
+        Ok(samples)
     }
 
-    pub fn speak_async(&self, text: &str) -> Option<SpeakHandle> {
-        let samples = self.synthesize(text);
+    pub fn speak_async(&self, text: &str) -> Result<Option<SpeakHandle>, TtsError> {
+        let samples = self.synthesize(text)?;
         if samples.is_empty() {
-            return None;
+            return Ok(None);
         }
-        let buf = SamplesBuffer::new(1, 22050, samples);
-        let (_stream, stream_handle) =
-            OutputStream::try_default().expect("Ошибка открытия аудио выхода");
-        let sink = Sink::try_new(&stream_handle).expect("Ошибка создания Sink");
-        sink.append(buf);
-        Some(SpeakHandle { sink, _stream })
+        let handle = self.open()?;
+        handle.push(text, samples);
+        Ok(Some(handle))
     }
 
-    pub fn speak(&self, text: &str) {
-        let samples = self.synthesize(text);
-        if samples.is_empty() {
-            return;
+    /// Open an empty playback handle for streamed replies; sentences are
+    /// queued onto it with `append` while earlier ones are still playing.
+    ///
+    /// The output device is opened anew for every handle, so a speaker that
+    /// was unplugged is picked up again once it is back.  After a failure,
+    /// further attempts fail fast until the backoff delay has passed.
+    pub fn open(&self) -> Result<SpeakHandle, TtsError> {
+        if let Some(at) = *self.output_retry_at.borrow() {
+            let wait = at.saturating_duration_since(Instant::now());
+            if !wait.is_zero() {
+                return Err(TtsError::Output(format!(
+                    "повтор через {} с",
+                    wait.as_secs() + 1
+                )));
+            }
+        }
+
+        let opened = OutputStream::try_default()
+            .map_err(|e| e.to_string())
+            .and_then(|(stream, handle)| {
+                Sink::try_new(&handle)
+                    .map(|sink| (stream, sink))
+                    .map_err(|e| e.to_string())
+            });
+        let (_stream, sink) = match opened {
+            Ok(o) => o,
+            Err(e) => {
+                let delay = self.output_backoff.borrow_mut().next_delay();
+                *self.output_retry_at.borrow_mut() = Some(Instant::now() + delay);
+                return Err(TtsError::Output(e));
+            }
+        };
+        self.output_backoff.borrow_mut().reset();
+        *self.output_retry_at.borrow_mut() = None;
+
+        Ok(SpeakHandle {
+            sink,
+            _stream,
+            segments: RefCell::new(Vec::new()),
+            echo: self.echo.clone(),
+            progress: RefCell::new((0, Duration::ZERO, Instant::now())),
+        })
+    }
+
+    /// Synthesize `text` and queue it after whatever the handle is playing.
+    pub fn append(&self, handle: &SpeakHandle, text: &str) -> Result<(), TtsError> {
+        let samples = self.synthesize(text)?;
+        if !samples.is_empty() {
+            handle.push(text, samples);
+        }
+        Ok(())
+    }
+
+    pub fn speak(&self, text: &str) -> Result<(), TtsError> {
+        if let Some(handle) = self.speak_async(text)? {
+            handle.sink.sleep_until_end();
         }
-        let buf = SamplesBuffer::new(1, 22050, samples);
-        let (_stream, stream_handle) =
-            OutputStream::try_default().expect("Ошибка открытия аудио выхода");
-        let sink = Sink::try_new(&stream_handle).expect("Ошибка создания Sink");
-        sink.append(buf);
-        sink.sleep_until_end();
+        Ok(())
     }
 }
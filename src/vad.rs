@@ -0,0 +1,210 @@
+use crate::audio::SAMPLE_RATE;
+use crate::config::{TimingConfig, VadConfig};
+
+const FRAME_MS: usize = 10;
+/// Share of 10 ms frames in a chunk that must be voiced for the chunk to count as speech.
+const SPEECH_FRAME_RATIO: f32 = 0.3;
+/// Speech has most of its energy below ~4 kHz; above this zero-crossing rate
+/// a loud frame is more likely hiss or clatter than voice.
+const MAX_SPEECH_ZCR: f32 = 0.35;
+/// Floor for the noise estimate, roughly a quiet room through a cheap mic.
+const MIN_NOISE_DB: f32 = -70.0;
+
+// ---------------------------------------------------------------------------
+// Vad — energy / zero-crossing voice activity detector
+// ---------------------------------------------------------------------------
+
+/// Classifies each audio chunk as speech or non-speech against an adaptive
+/// noise floor.  Cheap enough to run on every chunk on a Raspberry Pi.
+pub struct Vad {
+    threshold_db: f32,
+    hangover_chunks: u32,
+    noise_db: f32,
+    hangover: u32,
+}
+
+impl Vad {
+    pub fn new(config: &VadConfig, timing: &TimingConfig) -> Self {
+        Self {
+            threshold_db: config.threshold_db,
+            hangover_chunks: timing.chunks(config.hangover_ms),
+            noise_db: -50.0,
+            hangover: 0,
+        }
+    }
+
+    /// `true` if the chunk contains speech, or speech ended less than
+    /// `hangover_ms` ago (so trailing syllables are not cut off).
+    pub fn is_speech(&mut self, chunk: &[i16]) -> bool {
+        let frame_len = SAMPLE_RATE as usize * FRAME_MS / 1000;
+        let mut frames = 0;
+        let mut voiced = 0;
+
+        for frame in chunk.chunks(frame_len) {
+            let energy_db = energy_db(frame);
+            let zcr = zero_crossing_rate(frame);
+            frames += 1;
+
+            if energy_db > self.noise_db + self.threshold_db && zcr < MAX_SPEECH_ZCR {
+                voiced += 1;
+                // Creep up slowly so sustained noise is eventually absorbed.
+                self.noise_db += 0.01 * (energy_db - self.noise_db);
+            } else {
+                // Follow quieter frames quickly, louder ones slowly.
+                let rate = if energy_db < self.noise_db { 0.2 } else { 0.05 };
+                self.noise_db += rate * (energy_db - self.noise_db);
+            }
+            self.noise_db = self.noise_db.max(MIN_NOISE_DB);
+        }
+
+        if frames > 0 && voiced as f32 / frames as f32 >= SPEECH_FRAME_RATIO {
+            self.hangover = self.hangover_chunks;
+            true
+        } else if self.hangover > 0 {
+            self.hangover -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn energy_db(frame: &[i16]) -> f32 {
+    let mean_sq = frame
+        .iter()
+        .map(|&s| {
+            let x = s as f32 / i16::MAX as f32;
+            x * x
+        })
+        .sum::<f32>()
+        / frame.len().max(1) as f32;
+    10.0 * (mean_sq + 1e-10).log10()
+}
+
+fn zero_crossing_rate(frame: &[i16]) -> f32 {
+    let crossings = frame
+        .windows(2)
+        .filter(|w| (w[0] >= 0) != (w[1] >= 0))
+        .count();
+    crossings as f32 / frame.len().max(1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100 ms chunks, 10 dB threshold, hangover of 3 chunks.
+    fn detector() -> Vad {
+        let config = VadConfig {
+            enabled: true,
+            threshold_db: 10.0,
+            hangover_ms: 300,
+            skip_idle_silence: true,
+        };
+        let timing = TimingConfig {
+            chunk_ms: 100,
+            grace_ms: 300,
+            wake_timeout_ms: 500,
+            follow_up_ms: 500,
+            max_query_ms: 1000,
+        };
+        Vad::new(&config, &timing)
+    }
+
+    const CHUNK: usize = SAMPLE_RATE as usize / 10;
+
+    fn silence() -> Vec<i16> {
+        vec![0; CHUNK]
+    }
+
+    /// A 200 Hz sine with peak `amplitude` (0–1 of full scale).
+    fn tone(amplitude: f32) -> Vec<i16> {
+        (0..CHUNK)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                let x = amplitude * (2.0 * std::f32::consts::PI * 200.0 * t).sin();
+                (x * i16::MAX as f32) as i16
+            })
+            .collect()
+    }
+
+    /// Deterministic white noise in [-amplitude, amplitude).
+    fn hiss(amplitude: f32, seed: u32) -> Vec<i16> {
+        let mut state = seed;
+        (0..CHUNK)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let x = (state >> 8) as f32 / (1 << 23) as f32 - 1.0;
+                (amplitude * x * i16::MAX as f32) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn silence_is_not_speech() {
+        let mut vad = detector();
+        for _ in 0..20 {
+            assert!(!vad.is_speech(&silence()));
+        }
+        assert!(!vad.is_speech(&[]));
+    }
+
+    #[test]
+    fn tone_is_speech_and_hiss_is_not() {
+        let mut vad = detector();
+        assert!(!vad.is_speech(&silence()));
+        assert!(vad.is_speech(&tone(0.3)));
+
+        let mut vad = detector();
+        assert!(!vad.is_speech(&silence()));
+        for seed in 0..10 {
+            assert!(!vad.is_speech(&hiss(0.5, seed)));
+        }
+    }
+
+    #[test]
+    fn speech_is_held_for_the_hangover() {
+        let mut vad = detector();
+        for _ in 0..5 {
+            assert!(!vad.is_speech(&silence()));
+        }
+        assert!(vad.is_speech(&tone(0.3)));
+        let after: Vec<bool> = (0..5).map(|_| vad.is_speech(&silence())).collect();
+        assert_eq!(after, [true, true, true, false, false]);
+
+        // Speech during the hangover starts it over.
+        assert!(vad.is_speech(&tone(0.3)));
+        assert!(vad.is_speech(&silence()));
+        assert!(vad.is_speech(&tone(0.3)));
+        let after: Vec<bool> = (0..4).map(|_| vad.is_speech(&silence())).collect();
+        assert_eq!(after, [true, true, true, false]);
+    }
+
+    #[test]
+    fn short_bursts_need_enough_voiced_frames() {
+        let frame = SAMPLE_RATE as usize * FRAME_MS / 1000;
+        let burst = |frames: usize| {
+            let mut chunk = silence();
+            chunk[..frames * frame].copy_from_slice(&tone(0.3)[..frames * frame]);
+            chunk
+        };
+        assert!(!detector().is_speech(&burst(2)));
+        assert!(detector().is_speech(&burst(3)));
+    }
+
+    #[test]
+    fn noise_floor_follows_the_background() {
+        // Loud enough over a quiet room, but not over steady hiss.
+        let mut quiet = detector();
+        for _ in 0..20 {
+            quiet.is_speech(&silence());
+        }
+        assert!(quiet.is_speech(&tone(0.2)));
+
+        let mut noisy = detector();
+        for seed in 0..20 {
+            assert!(!noisy.is_speech(&hiss(0.25, seed)));
+        }
+        assert!(!noisy.is_speech(&tone(0.2)));
+    }
+}
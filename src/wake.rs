@@ -0,0 +1,377 @@
+use voskrust::api::{Model, Recognizer};
+
+use crate::audio::SAMPLE_RATE;
+use crate::config::{AssistantConfig, PersonaConfig};
+
+/// Longest utterance kept for re-recognition by the full model.
+const MAX_UTTERANCE_SAMPLES: usize = 10 * SAMPLE_RATE as usize;
+/// Vosk marks words outside the grammar with this token.
+const UNKNOWN_WORD: &str = "[unk]";
+
+// ---------------------------------------------------------------------------
+// WakeMatcher — word-aligned, optionally fuzzy wake word search
+// ---------------------------------------------------------------------------
+
+/// A wake word variant found in recognized text.
+pub struct WakeMatch {
+    /// Index of the persona the variant belongs to.
+    pub persona: usize,
+    pub variant: String,
+    /// The words that matched it, as recognized.
+    pub heard: String,
+    /// Similarity in 0–1, 1 being an exact match.
+    pub score: f32,
+    /// Text following the matched words.
+    pub remainder: String,
+}
+
+pub struct WakeMatcher {
+    /// Persona index, variant as configured and its comparison form.
+    variants: Vec<(usize, String, Vec<char>)>,
+    min_similarity: f32,
+    phonetic: bool,
+}
+
+impl WakeMatcher {
+    pub fn new(personas: &[PersonaConfig], config: &AssistantConfig) -> Self {
+        let phonetic = config.wake_phonetic;
+        let variants = personas
+            .iter()
+            .enumerate()
+            .flat_map(|(i, p)| p.wake_word.iter().map(move |v| (i, v)))
+            .map(|(i, v)| {
+                let words: Vec<&str> = v.split_whitespace().collect();
+                (i, v.clone(), normalize(&words, phonetic))
+            })
+            .filter(|(_, _, key)| !key.is_empty())
+            .collect();
+        Self {
+            variants,
+            min_similarity: config.wake_similarity.clamp(0.0, 1.0),
+            phonetic,
+        }
+    }
+
+    /// Best-scoring variant matching whole words of `text`, earliest first on ties.
+    pub fn find(&self, text: &str) -> Option<WakeMatch> {
+        let words: Vec<(usize, &str)> = word_spans(text);
+        let mut best: Option<(f32, usize, &str, usize, usize)> = None;
+
+        for (persona, variant, key) in &self.variants {
+            let len = variant.split_whitespace().count();
+            for start in 0..words.len().saturating_sub(len - 1) {
+                let window: Vec<&str> = words[start..start + len].iter().map(|(_, w)| *w).collect();
+                let score = similarity(key, &normalize(&window, self.phonetic));
+                if score >= self.min_similarity && best.is_none_or(|(s, ..)| score > s) {
+                    best = Some((score, *persona, variant, start, start + len));
+                }
+            }
+        }
+
+        let (score, persona, variant, start, end) = best?;
+        let from = words[start].0;
+        let (last_at, last) = words[end - 1];
+        let to = last_at + last.len();
+        Some(WakeMatch {
+            persona,
+            variant: variant.to_string(),
+            heard: text[from..to].to_string(),
+            score,
+            remainder: text[to..].trim().to_string(),
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// WakeSpotter — grammar-restricted recognizer for the idle state
+// ---------------------------------------------------------------------------
+
+/// Listens for the wake word with a recognizer limited to its variants,
+/// which is far cheaper than the full vocabulary.  Utterances in which it
+/// hears one are re-recognized with the full model so that the query spoken
+/// right after the wake word is not lost.
+pub struct WakeSpotter<'a> {
+    model: &'a Model,
+    recognizer: Recognizer<'a>,
+    /// Audio of the current utterance.
+    utterance: Vec<i16>,
+}
+
+impl<'a> WakeSpotter<'a> {
+    pub fn new(model: &'a Model, personas: &[PersonaConfig]) -> Self {
+        Self {
+            model,
+            recognizer: Recognizer::new_with_grammar(model, SAMPLE_RATE as f32, &grammar(personas)),
+            utterance: Vec::new(),
+        }
+    }
+
+    /// Feed one chunk.  When an utterance that may contain the wake word
+    /// ends, returns its full-model transcript.
+    pub fn accept(&mut self, chunk: &[i16]) -> Option<String> {
+        self.utterance.extend_from_slice(chunk);
+        let excess = self.utterance.len().saturating_sub(MAX_UTTERANCE_SAMPLES);
+        self.utterance.drain(..excess);
+
+        if !self.recognizer.accept_waveform(chunk) {
+            return None;
+        }
+        let text = self.recognizer.final_result();
+        self.finish(&text)
+    }
+
+    /// End the current utterance early, e.g. when silence is no longer fed.
+    pub fn flush(&mut self) -> Option<String> {
+        if self.utterance.is_empty() {
+            return None;
+        }
+        let text = self.recognizer.final_result();
+        self.finish(&text)
+    }
+
+    fn finish(&mut self, grammar_text: &str) -> Option<String> {
+        let audio = std::mem::take(&mut self.utterance);
+        if !heard_wake_word(grammar_text) {
+            return None;
+        }
+
+        let mut full = Recognizer::new(self.model, SAMPLE_RATE as f32);
+        let mut texts = Vec::new();
+        for chunk in audio.chunks(SAMPLE_RATE as usize / 10) {
+            if full.accept_waveform(chunk) {
+                texts.push(full.final_result());
+            }
+        }
+        texts.push(full.final_result());
+        texts.retain(|t| !t.is_empty());
+        Some(texts.join(" "))
+    }
+}
+
+/// Vosk grammar of all wake word variants, plus the unknown-word token so
+/// that other speech is not forced onto a variant.
+fn grammar(personas: &[PersonaConfig]) -> String {
+    let mut phrases: Vec<String> = personas
+        .iter()
+        .flat_map(|p| &p.wake_word)
+        .map(|v| v.to_lowercase())
+        .collect();
+    phrases.push(UNKNOWN_WORD.into());
+    serde_json::to_string(&phrases).unwrap()
+}
+
+/// Whether the grammar recognizer heard anything besides unknown words,
+/// i.e. the utterance is worth re-recognizing with the full model.
+fn heard_wake_word(grammar_text: &str) -> bool {
+    !grammar_text.split_whitespace().all(|w| w == UNKNOWN_WORD)
+}
+
+/// Words of `text` with their byte offsets.
+fn word_spans(text: &str) -> Vec<(usize, &str)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric() || c == '-', start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, &text[s..]));
+    }
+    spans
+}
+
+/// Lowercased letters of `words` joined by single spaces; with `phonetic`,
+/// folded so that Russian spellings that sound alike compare equal.
+fn normalize(words: &[&str], phonetic: bool) -> Vec<char> {
+    let joined = words.join(" ").to_lowercase();
+    if !phonetic {
+        return joined.chars().collect();
+    }
+
+    let mut out: Vec<char> = Vec::with_capacity(joined.len());
+    for c in joined.chars() {
+        let folded = match c {
+            'ё' | 'э' => 'е',
+            'о' => 'а',
+            'ы' | 'й' => 'и',
+            'ю' => 'у',
+            'я' => 'а',
+            'б' => 'п',
+            'в' => 'ф',
+            'г' => 'к',
+            'д' => 'т',
+            'ж' => 'ш',
+            'з' => 'с',
+            'щ' => 'ш',
+            'ь' | 'ъ' => continue,
+            c => c,
+        };
+        // Doubled letters are rarely heard as such.
+        if out.last() != Some(&folded) {
+            out.push(folded);
+        }
+    }
+    out
+}
+
+/// 1 − Levenshtein distance / length of the longer string.
+fn similarity(a: &[char], b: &[char]) -> f32 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    1.0 - row[b.len()] as f32 / longest as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn persona(wake_words: &[&str]) -> PersonaConfig {
+        PersonaConfig {
+            name: wake_words[0].into(),
+            wake_word: wake_words.iter().map(|w| w.to_string()).collect(),
+            system_prompt: String::new(),
+            voice: String::new(),
+            model: String::new(),
+            tools: None,
+        }
+    }
+
+    fn matcher(similarity: f32, phonetic: bool) -> WakeMatcher {
+        let config: AssistantConfig = toml::from_str(&format!(
+            "wake_similarity = {}\nwake_phonetic = {}",
+            similarity, phonetic
+        ))
+        .unwrap();
+        WakeMatcher::new(
+            &[persona(&["джарвис"]), persona(&["алиса", "окей дом"])],
+            &config,
+        )
+    }
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn grammar_lists_lowercased_variants_and_unknown() {
+        let personas = [persona(&["Джарвис"]), persona(&["алиса", "Окей дом"])];
+        let phrases: Vec<String> = serde_json::from_str(&grammar(&personas)).unwrap();
+        assert_eq!(phrases, ["джарвис", "алиса", "окей дом", "[unk]"]);
+    }
+
+    #[test]
+    fn only_wake_words_are_handed_to_the_full_model() {
+        assert!(heard_wake_word("джарвис"));
+        assert!(heard_wake_word("[unk] [unk] алиса [unk]"));
+        assert!(heard_wake_word("окей дом"));
+        assert!(!heard_wake_word(""));
+        assert!(!heard_wake_word("  "));
+        assert!(!heard_wake_word("[unk]"));
+        assert!(!heard_wake_word("[unk] [unk]"));
+    }
+
+    #[test]
+    fn similarity_is_one_minus_relative_distance() {
+        assert_eq!(similarity(&chars("джарвис"), &chars("джарвис")), 1.0);
+        assert_eq!(similarity(&[], &[]), 1.0);
+        assert_eq!(similarity(&chars("abc"), &[]), 0.0);
+        // One substitution or deletion in seven letters, one insertion in eight.
+        for heard in ["джервис", "жарвис"] {
+            let score = similarity(&chars("джарвис"), &chars(heard));
+            assert!((score - 6.0 / 7.0).abs() < 1e-6, "{}: {}", heard, score);
+        }
+        let score = similarity(&chars("джарвис"), &chars("джарвиис"));
+        assert!((score - 7.0 / 8.0).abs() < 1e-6);
+        assert!((similarity(&chars("джарвис"), &chars("дарвин")) - 5.0 / 7.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn normalize_folds_sounds_alike() {
+        assert_eq!(normalize(&["Джарвис"], false), chars("джарвис"));
+        assert_eq!(normalize(&["окей", "дом"], false), chars("окей дом"));
+        assert_eq!(normalize(&["Джарвис"], true), chars("тшарфис"));
+        assert_eq!(normalize(&["джорвиз"], true), normalize(&["джарвис"], true));
+        // Soft signs vanish and doubled letters collapse.
+        assert_eq!(normalize(&["алла"], true), chars("ала"));
+        assert_eq!(normalize(&["мать"], true), chars("мат"));
+    }
+
+    #[test]
+    fn word_spans_keep_byte_offsets() {
+        assert_eq!(
+            word_spans("Эй, Джарвис! Кто-то"),
+            vec![(0, "Эй"), (6, "Джарвис"), (22, "Кто-то")]
+        );
+        assert!(word_spans(" ,. ").is_empty());
+    }
+
+    #[test]
+    fn finds_whole_words_only() {
+        let m = matcher(1.0, false).find("эй джарвис включи свет").unwrap();
+        assert_eq!((m.persona, m.variant.as_str()), (0, "джарвис"));
+        assert_eq!(m.heard, "джарвис");
+        assert_eq!(m.remainder, "включи свет");
+        assert_eq!(m.score, 1.0);
+
+        assert!(matcher(1.0, false).find("джарвисон включи свет").is_none());
+        assert!(matcher(1.0, false).find("").is_none());
+    }
+
+    #[test]
+    fn multi_word_variant_spans_punctuation() {
+        let m = matcher(1.0, false).find("Окей, дом: который час?").unwrap();
+        assert_eq!((m.persona, m.variant.as_str()), (1, "окей дом"));
+        assert_eq!(m.heard, "Окей, дом");
+        assert_eq!(m.remainder, ": который час?");
+    }
+
+    #[test]
+    fn near_misses_pass_the_threshold() {
+        let m = matcher(0.8, false).find("джервис который час").unwrap();
+        assert_eq!(m.variant, "джарвис");
+        assert_eq!(m.heard, "джервис");
+        assert!(m.score < 1.0);
+        assert_eq!(m.remainder, "который час");
+
+        // Exact by default, and a better match wins over an earlier one.
+        assert!(matcher(1.0, false).find("джервис который час").is_none());
+        let m = matcher(0.8, false).find("джервис джарвис").unwrap();
+        assert_eq!((m.heard.as_str(), m.score), ("джарвис", 1.0));
+    }
+
+    #[test]
+    fn rejects_words_too_far_off() {
+        assert!(matcher(0.8, false).find("дарвин сказал").is_none());
+        assert!(matcher(0.8, false).find("включи свет").is_none());
+        assert!(matcher(0.8, true).find("василиса").is_none());
+    }
+
+    #[test]
+    fn phonetic_matching_forgives_spelling() {
+        assert!(matcher(1.0, false).find("джорвиз").is_none());
+        let m = matcher(1.0, true).find("Джорвиз, свет").unwrap();
+        assert_eq!(
+            (m.variant.as_str(), m.heard.as_str()),
+            ("джарвис", "Джорвиз")
+        );
+        assert_eq!(m.remainder, ", свет");
+    }
+}